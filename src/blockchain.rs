@@ -3,40 +3,361 @@ extern crate serde_json;
 extern crate sha2;
 extern crate time;
 
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
 use std::fmt::Write;
-use std::time::SystemTime;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 // Used to serialize and deserialize json
 // https://serde.rs/derive.html
-#[derive(Debug, Clone, Serialize)]
-struct Transaction {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
     sender: String,
     receiver: String,
     amount: f32,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl Transaction {
+    pub fn new(sender: String, receiver: String, amount: f32) -> Transaction {
+        Transaction {
+            sender,
+            receiver,
+            amount,
+        }
+    }
+
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
+
+    // Same hash used for the merkle leaves
+    pub fn id(&self) -> String {
+        Chain::hash(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransactionError {
+    UnknownSender,
+    UnknownReceiver,
+    InsufficientBalance,
+    DustAmount,
+    InvalidAmount,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::UnknownSender => write!(f, "Sender not found!"),
+            TransactionError::UnknownReceiver => write!(f, "Receiver not found!"),
+            TransactionError::InsufficientBalance => write!(f, "insufficient balance"),
+            TransactionError::DustAmount => write!(f, "amount below dust threshold"),
+            TransactionError::InvalidAmount => write!(f, "amount must be a positive number"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Header {
     timestamp: std::time::SystemTime,
     nonce: u32,
     pre_hash: String,
     merkle_root: String,
     difficulty: u32,
+    message: String,
+}
+
+impl Header {
+    pub fn nonce(&self) -> u32 {
+        self.nonce
+    }
+
+    pub fn set_nonce(&mut self, nonce: u32) {
+        self.nonce = nonce;
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     header: Header,
     count: u32,
     transactions: Vec<Transaction>,
 }
 
+// Settings fixed when the chain is created
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    // Embedded in the genesis header, so it is covered by the genesis hash
+    pub genesis_message: String,
+    // Fixed genesis timestamp, the current time is used when None
+    pub genesis_timestamp: Option<SystemTime>,
+    // Reject transfers to addresses without a balance, guards against typos
+    pub require_known_receiver: bool,
+    // A new block must be newer than the median timestamp of this many previous blocks
+    pub median_time_window: usize,
+    // Transfers below this amount are rejected, miner rewards are exempt
+    pub dust_threshold: f32,
+    // Upper bound on the serialized size of a block, checked before anything else
+    pub max_block_bytes: Option<usize>,
+    // Used by the default proof of work, see LeadingZeros::yield_interval
+    pub mining_yield_interval: u32,
+    // Difficulties below this get a security_warning
+    pub min_safe_difficulty: u32,
+    // Blocks below this difficulty are rejected everywhere, including in segments and
+    // saved chains that are checked without this chain's current difficulty
+    pub min_difficulty: u32,
+    // Trusted (height, cumulative work up to and including that height)
+    pub work_checkpoint: Option<(usize, u128)>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> ChainConfig {
+        ChainConfig {
+            genesis_message: String::new(),
+            genesis_timestamp: None,
+            require_known_receiver: false,
+            median_time_window: 11,
+            dust_threshold: 0.0,
+            max_block_bytes: None,
+            mining_yield_interval: 0,
+            min_safe_difficulty: 2,
+            min_difficulty: 1,
+            work_checkpoint: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistributionStats {
+    pub accounts: usize,
+    pub total_supply: f32,
+    // 0 when everyone holds the same amount, close to 1 when one account holds everything
+    pub gini: f32,
+    // Fraction of the total supply held by the richest account
+    pub top_share: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    BrokenLink,
+    InvalidMerkleRoot,
+    InvalidProofOfWork,
+    WrongDifficulty,
+    InvalidCoinbase,
+    CoinbaseReceiverMismatch,
+    TimestampTooOld,
+    TooLarge,
+    Overdraw,
+    InvalidTransaction(TransactionError),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::BrokenLink => write!(f, "pre_hash does not match the last block"),
+            BlockError::InvalidMerkleRoot => write!(f, "merkle root does not match transactions"),
+            BlockError::InvalidProofOfWork => write!(f, "block hash does not meet difficulty"),
+            BlockError::WrongDifficulty => {
+                write!(f, "block difficulty is not the chain difficulty")
+            }
+            BlockError::InvalidCoinbase => write!(f, "invalid miner reward transaction"),
+            BlockError::CoinbaseReceiverMismatch => write!(f, "reward not paid to this miner"),
+            BlockError::TimestampTooOld => write!(f, "timestamp not after median time past"),
+            BlockError::TooLarge => write!(f, "block exceeds the maximum size"),
+            BlockError::Overdraw => write!(f, "a sender spends more than its balance"),
+            BlockError::InvalidTransaction(err) => write!(f, "invalid transaction: {}", err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MineError {
+    // Not even a block with just the miner reward fits in max_block_bytes
+    TooLarge,
+}
+
+impl fmt::Display for MineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MineError::TooLarge => write!(f, "an empty block exceeds the maximum size"),
+        }
+    }
+}
+
+// Contents of the next block, transactions are the pending ones that fit, in block
+// order, and the merkle root also covers the miner reward
+#[derive(Debug, Clone)]
+pub struct BlockPreview {
+    pub transactions: Vec<Transaction>,
+    pub reward: f32,
+    pub merkle_root: String,
+}
+
+// Position of the first bad block in a segment and why it was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentError {
+    pub index: usize,
+    pub error: BlockError,
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block {} of the segment: {}", self.index, self.error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainError {
+    HeightOutOfRange,
+    InvalidBlock(SegmentError),
+    CheckpointMismatch,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChainError::HeightOutOfRange => write!(f, "height is above the chain tip"),
+            ChainError::InvalidBlock(err) => write!(f, "{}", err),
+            ChainError::CheckpointMismatch => write!(f, "cumulative work differs from checkpoint"),
+        }
+    }
+}
+
+// A sha256 hash has 64 hex digits, no header can meet a higher difficulty. From
+// difficulty 8 on the u32 nonce alone is often not enough, see Chain::proof_of_work.
+pub const MAX_DIFFICULTY: u32 = 64;
+
+// Mining scheme used to seal block headers, so alternatives can be plugged in
+pub trait ProofOfWork {
+    // Makes the header valid, by setting the nonce and if needed moving the timestamp
+    // forward, and returns the resulting header hash
+    fn solve(&self, header: &mut Header) -> String;
+    fn verify(&self, header: &Header) -> bool;
+}
+
+// Default scheme, the header hash needs `difficulty` leading zero hex digits
+#[derive(Debug, Clone, Default)]
+pub struct LeadingZeros {
+    // Give up the CPU every this many nonce attempts, 0 never yields
+    pub yield_interval: u32,
+}
+
+impl ProofOfWork for LeadingZeros {
+    fn solve(&self, header: &mut Header) -> String {
+        Chain::proof_of_work(header, self.yield_interval)
+    }
+
+    fn verify(&self, header: &Header) -> bool {
+        Chain::meets_difficulty(&Chain::hash_bytes(header), header.difficulty)
+    }
+}
+
+// When the auto-miner closes a batch of pending transactions and mines it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchWindow {
+    // As soon as this many transactions are pending
+    Count(usize),
+    // This long after the first transaction of the batch arrived
+    Time(Duration),
+}
+
+// Probabilistic set of addresses: may_contain never misses an inserted address,
+// but can report a few false positives
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<bool>,
+}
+
+impl BloomFilter {
+    // Number of bit positions set per address, each taken from 4 bytes of its sha256
+    const HASHES: usize = 4;
+    // About 10 bits per address keeps false positives near 1% with 4 hashes
+    const BITS_PER_ITEM: usize = 10;
+
+    pub fn new(expected_items: usize) -> BloomFilter {
+        BloomFilter {
+            bits: vec![false; (expected_items * BloomFilter::BITS_PER_ITEM).max(64)],
+        }
+    }
+
+    pub fn insert(&mut self, address: &str) {
+        for index in self.positions(address) {
+            self.bits[index] = true;
+        }
+    }
+
+    pub fn may_contain(&self, address: &str) -> bool {
+        self.positions(address)
+            .iter()
+            .all(|index| self.bits[*index])
+    }
+
+    fn positions(&self, address: &str) -> Vec<usize> {
+        let digest = Chain::hash_bytes(&address);
+
+        digest
+            .chunks(4)
+            .take(BloomFilter::HASHES)
+            .map(|chunk| {
+                let value = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                value as usize % self.bits.len()
+            })
+            .collect()
+    }
+}
+
+// Full copy of a node's chain state, see Chain::checkpoint_state
+#[derive(Debug, Clone)]
+pub struct NodeState {
+    config: ChainConfig,
+    records: HashMap<String, f32>,
+    chain: Vec<Block>,
+    current_transaction: Vec<Transaction>,
+    difficulty: u32,
+    miner_address: String,
+    reward: f32,
+}
+
+// Written next to the block files by Chain::save_to_dir
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    blocks: usize,
+    miner_address: String,
+    difficulty: u32,
+    reward: f32,
+}
+
+// Custom transaction policy, an Err rejects the transaction with the given reason
+pub type Validator = Box<dyn Fn(&Transaction, &Chain) -> Result<(), String>>;
+
 pub struct Chain {
+    config: ChainConfig,
+    pow: Box<dyn ProofOfWork>,
+    validators: Vec<Validator>,
     records: HashMap<String, f32>,
     chain: Vec<Block>,
+    // Hash of the last block header, as returned when it was mined
+    tip_hash: String,
     current_transaction: Vec<Transaction>,
     difficulty: u32,
     miner_address: String,
@@ -45,204 +366,2727 @@ pub struct Chain {
 
 impl Chain {
     pub fn new(miner_address: String, difficulty: u32) -> Chain {
-        let mut chain = Chain {
+        Chain::with_config(miner_address, difficulty, ChainConfig::default())
+    }
+
+    pub fn with_config(miner_address: String, difficulty: u32, config: ChainConfig) -> Chain {
+        let pow = LeadingZeros {
+            yield_interval: config.mining_yield_interval,
+        };
+        Chain::with_proof_of_work(miner_address, difficulty, config, Box::new(pow))
+    }
+
+    // The genesis block is already mined with `pow`, so the whole chain shares one scheme
+    pub fn with_proof_of_work(
+        miner_address: String,
+        difficulty: u32,
+        config: ChainConfig,
+        pow: Box<dyn ProofOfWork>,
+    ) -> Chain {
+        assert!(
+            difficulty >= config.min_difficulty && difficulty <= MAX_DIFFICULTY,
+            "difficulty must be between {} and {}",
+            config.min_difficulty,
+            MAX_DIFFICULTY
+        );
+        let mut chain = Chain::without_blocks(miner_address, difficulty, config, pow);
+
+        // Same config, miner and difficulty give the same genesis block
+        let timestamp = chain
+            .config
+            .genesis_timestamp
+            .unwrap_or_else(SystemTime::now);
+        let message = chain.config.genesis_message.clone();
+        let miner_address = chain.miner_address.clone();
+        assert!(
+            chain.mine_block(timestamp, message, miner_address),
+            "max_block_bytes is too small for the genesis block"
+        );
+        chain
+    }
+
+    fn without_blocks(
+        miner_address: String,
+        difficulty: u32,
+        config: ChainConfig,
+        pow: Box<dyn ProofOfWork>,
+    ) -> Chain {
+        Chain {
+            config,
+            pow,
+            validators: Vec::new(),
             records: HashMap::new(),
             chain: Vec::new(),
+            tip_hash: Chain::zero_hash(),
             current_transaction: Vec::new(),
             difficulty,
             miner_address,
             reward: 100.0,
+        }
+    }
+
+    // Writes block_<height>.json for every block plus a manifest.json with the block
+    // count and chain settings. Pending transactions are not saved.
+    pub fn save_to_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+
+        for (height, block) in self.chain.iter().enumerate() {
+            let file = fs::File::create(dir.join(format!("block_{}.json", height)))?;
+            serde_json::to_writer(file, block)?;
+        }
+
+        let manifest = Manifest {
+            blocks: self.chain.len(),
+            miner_address: self.miner_address.clone(),
+            difficulty: self.difficulty,
+            reward: self.reward,
         };
+        let file = fs::File::create(dir.join("manifest.json"))?;
+        serde_json::to_writer_pretty(file, &manifest)?;
 
-        chain.generate_new_block();
+        Ok(())
+    }
+
+    // Reads back a directory written by save_to_dir, checking the blocks with
+    // validate_chain, which replays them on one running ledger, and rebuilding the
+    // balances from them. The chain gets the default
+    // config and proof of work, as neither is part of the saved files.
+    pub fn load_from_dir(dir: &Path) -> io::Result<Chain> {
+        let file = fs::File::open(dir.join("manifest.json"))?;
+        let manifest: Manifest = serde_json::from_reader(file)?;
+        let config = ChainConfig::default();
+        // The same settings with_proof_of_work and update_reward would accept
+        if manifest.difficulty < config.min_difficulty || manifest.difficulty > MAX_DIFFICULTY {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "difficulty out of range",
+            ));
+        }
+        if !manifest.reward.is_finite() || manifest.reward <= 0.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid reward"));
+        }
+
+        // The manifest block count is not trusted for an allocation, a missing block
+        // file ends the load instead
+        let mut blocks = Vec::new();
+        for height in 0..manifest.blocks {
+            let file = fs::File::open(dir.join(format!("block_{}.json", height)))?;
+            blocks.push(serde_json::from_reader(file)?);
+        }
+
+        let pow = LeadingZeros {
+            yield_interval: config.mining_yield_interval,
+        };
+        let mut chain = Chain::without_blocks(
+            manifest.miner_address,
+            manifest.difficulty,
+            config,
+            Box::new(pow),
+        );
+        chain.reward = manifest.reward;
+
+        if blocks.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "no blocks"));
+        }
         chain
+            .validate_chain(&blocks)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+
+        chain.chain = blocks;
+        chain.rehash_tip();
+        chain.records = chain.confirmed_balances();
+        Ok(chain)
     }
 
     pub fn new_transaction(&mut self, sender: String, receiver: String, amount: f32) -> bool {
-        if self.check_transfer_availability(&sender, &receiver, amount) != true {
+        let transaction = Transaction {
+            sender,
+            receiver,
+            amount,
+        };
+
+        for validator in &self.validators {
+            if let Err(reason) = validator(&transaction, self) {
+                println!("{}", reason);
+                println!("Unable to complete the transaction");
+                return false;
+            }
+        }
+
+        if !self.check_transfer_availability(&transaction.sender, &transaction.receiver, amount) {
             println!("Unable to complete the transaction");
             return false;
         }
 
-        self.current_transaction.push(Transaction {
-            sender,
-            receiver,
-            amount,
-        });
+        self.current_transaction.push(transaction);
 
         true
     }
 
-    pub fn last_hash(&self) -> String {
-        let block = match self.chain.last() {
-            Some(block) => block, // If exists at least one (last) block, use it
-            None => return String::from_utf8(vec![48; 64]).unwrap(), // else, we're dealing with the genesis block and we must create the first hash
-        };
+    // Runs on every new transaction before the built-in balance checks
+    pub fn add_validator<F>(&mut self, validator: F)
+    where
+        F: Fn(&Transaction, &Chain) -> Result<(), String> + 'static,
+    {
+        self.validators.push(Box::new(validator));
+    }
 
-        Chain::hash(&block.header)
+    // Deep copy of the chain, ledger, mempool and settings. The proof of work and the
+    // validators are behaviour rather than state and are not part of it.
+    pub fn checkpoint_state(&self) -> NodeState {
+        NodeState {
+            config: self.config.clone(),
+            records: self.records.clone(),
+            chain: self.chain.clone(),
+            current_transaction: self.current_transaction.clone(),
+            difficulty: self.difficulty,
+            miner_address: self.miner_address.clone(),
+            reward: self.reward,
+        }
     }
 
-    pub fn update_difficulty(&mut self, difficulty: u32) -> bool {
-        self.difficulty = difficulty;
-        true
+    pub fn restore_state(&mut self, state: NodeState) {
+        self.config = state.config;
+        self.records = state.records;
+        self.chain = state.chain;
+        self.rehash_tip();
+        self.current_transaction = state.current_transaction;
+        self.difficulty = state.difficulty;
+        self.miner_address = state.miner_address;
+        self.reward = state.reward;
     }
 
-    pub fn update_reward(&mut self, reward: f32) -> bool {
-        self.reward = reward;
-        true
+    pub fn export_mempool(&self) -> Vec<Transaction> {
+        self.current_transaction.clone()
     }
 
-    pub fn generate_new_block(&mut self) -> bool {
-        let header = Header {
-            timestamp: SystemTime::now(),
-            nonce: 0,
-            merkle_root: String::new(),
-            pre_hash: self.last_hash(),
-            difficulty: self.difficulty,
-        };
+    // Queues each transaction again through the usual checks, returns how many were accepted
+    pub fn import_mempool(&mut self, txs: Vec<Transaction>) -> usize {
+        let mut imported = 0;
 
-        let transaction_reward = Transaction {
-            sender: String::from("Root"),
-            receiver: self.miner_address.clone(),
-            amount: self.reward,
-        };
+        for tx in txs {
+            if self.new_transaction(tx.sender, tx.receiver, tx.amount) {
+                imported += 1;
+            }
+        }
 
-        let mut block = Block {
-            header,
-            count: 0,
-            transactions: vec![],
+        imported
+    }
+
+    pub fn block(&self, height: usize) -> Option<&Block> {
+        self.chain.get(height)
+    }
+
+    // Up to the last `n` blocks, oldest first
+    pub fn recent_blocks(&self, n: usize) -> &[Block] {
+        &self.chain[self.chain.len().saturating_sub(n)..]
+    }
+
+    // Height of the tip, genesis is 0
+    pub fn height(&self) -> usize {
+        self.chain.len() - 1
+    }
+
+    pub fn balance(&self, address: &str) -> Option<f32> {
+        self.records.get(address).cloned()
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    pub fn reward(&self) -> f32 {
+        self.reward
+    }
+
+    pub fn last_hash(&self) -> String {
+        self.tip_hash.clone()
+    }
+
+    // Refreshes tip_hash after the blocks were replaced without mining
+    fn rehash_tip(&mut self) {
+        self.tip_hash = match self.chain.last() {
+            Some(block) => Chain::hash(&block.header), // If exists at least one (last) block, use it
+            None => Chain::zero_hash(), // else, we're dealing with the genesis block and we must create the first hash
         };
+    }
 
-        // Miner reward
-        block.transactions.push(transaction_reward);
-        // All Block transactions
-        block.transactions.append(&mut self.current_transaction);
-        block.count = block.transactions.len() as u32;
-        block.header.merkle_root = Chain::get_merkle(block.transactions.clone());
-        Chain::proof_of_work(&mut block.header);
+    // pre_hash of the genesis block
+    fn zero_hash() -> String {
+        String::from_utf8(vec![48; 64]).unwrap()
+    }
 
-        // Add mined coins to the receiver address
-        let receiver = &self.miner_address;
-        match self.records.get_mut(receiver) {
-            Some(_val) => {
-                *self.records.get_mut(receiver).unwrap() += self.reward;
-                println!("Added {} coins to address {}", self.reward, receiver);
-            }
-            None => {
-                self.records.insert(receiver.to_string(), self.reward);
-                println!("Added {} coins to address {}", self.reward, receiver);
+    // Folds over every transaction in the chain, passing along the height of its block
+    pub fn fold_transactions<B, F: FnMut(B, usize, &Transaction) -> B>(
+        &self,
+        init: B,
+        mut f: F,
+    ) -> B {
+        let mut acc = init;
+
+        for (height, block) in self.chain.iter().enumerate() {
+            for transaction in &block.transactions {
+                acc = f(acc, height, transaction);
             }
         }
 
-        println!("{:#?}", &block);
-        self.chain.push(block);
-        true
+        acc
     }
 
-    fn get_merkle(current_transactions: Vec<Transaction>) -> String {
-        let mut merkle = Vec::new();
+    // records is a HashMap, sort by address for a reproducible listing
+    pub fn balances_sorted(&self) -> Vec<(String, f32)> {
+        let mut balances: Vec<(String, f32)> = self
+            .records
+            .iter()
+            .map(|(address, balance)| (address.clone(), *balance))
+            .collect();
 
-        for transaction in &current_transactions {
-            let hash = Chain::hash(transaction);
-            merkle.push(hash);
-        }
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        balances
+    }
 
-        if merkle.len() % 2 == 1 {
-            let last = merkle.last().cloned().unwrap();
-            merkle.push(last);
+    // Applies the transactions to a copy of the balances, the chain is left untouched
+    pub fn simulate(&self, txs: &[Transaction]) -> Result<HashMap<String, f32>, TransactionError> {
+        let mut records = self.records.clone();
+
+        for tx in txs {
+            self.validate_transfer(&records, &tx.sender, &tx.receiver, tx.amount)?;
+            Chain::transfer(&mut records, &tx.sender, &tx.receiver, tx.amount);
         }
 
-        while merkle.len() > 1 {
-            // Get the next two (first) hashes
-            let mut hash1 = merkle.remove(0);
-            let mut hash2 = merkle.remove(0);
+        Ok(records)
+    }
 
-            // Creates a hash based on the two previous hashes
-            hash1.push_str(&mut hash2);
-            let mergedHash = Chain::hash(&hash1);
+    pub fn balance_distribution(&self) -> DistributionStats {
+        let mut balances: Vec<f32> = self.records.values().cloned().collect();
+        balances.sort_by(|a, b| a.total_cmp(b));
 
-            // Put it back on the merkle_root vector
-            merkle.push(mergedHash);
+        let accounts = balances.len();
+        let total_supply: f32 = balances.iter().sum();
+
+        if accounts == 0 || total_supply <= 0.0 {
+            return DistributionStats {
+                accounts,
+                total_supply,
+                gini: 0.0,
+                top_share: 0.0,
+            };
         }
 
-        merkle.pop().unwrap()
-    }
+        // Gini over the balances sorted ascending (i starting at 1):
+        // G = 2 * sum(i * x_i) / (n * sum(x)) - (n + 1) / n
+        let n = accounts as f32;
+        let weighted: f32 = balances
+            .iter()
+            .enumerate()
+            .map(|(i, balance)| (i + 1) as f32 * balance)
+            .sum();
+        let gini = 2.0 * weighted / (n * total_supply) - (n + 1.0) / n;
 
-    pub fn proof_of_work(header: &mut Header) {
-        loop {
-            let hash = Chain::hash(header);
-            println!("hash: {}", hash);
-            let slice = &hash[..header.difficulty as usize];
-            println!("slice: {}", slice);
-            match slice.parse::<u32>() {
-                Ok(val) => {
-                    println!("val: {}", val);
-                    if val != 0 {
-                        header.nonce += 1;
-                    } else {
-                        println!("Block hash: {}", hash);
-                        break;
-                    }
-                }
-                Err(_) => {
-                    header.nonce += 1;
-                    continue;
-                }
-            };
+        DistributionStats {
+            accounts,
+            total_supply,
+            gini,
+            top_share: balances[accounts - 1] / total_supply,
         }
     }
 
-    // Generic T here will be a type of serde.Serialize
-    pub fn hash<T: serde::Serialize>(item: &T) -> String {
-        let input = serde_json::to_string(&item).unwrap();
-        let mut hasher = Sha256::default();
+    // Time between each pair of consecutive blocks, a timestamp going backwards counts as zero
+    pub fn block_intervals(&self) -> Vec<Duration> {
+        self.chain
+            .windows(2)
+            .map(|pair| {
+                pair[1]
+                    .header
+                    .timestamp
+                    .duration_since(pair[0].header.timestamp)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
 
-        hasher.update(input.as_bytes());
-        let res = hasher.finalize();
+    // Time from the genesis block to the tip, None while there is only genesis
+    pub fn chain_age(&self) -> Option<Duration> {
+        if self.chain.len() < 2 {
+            return None;
+        }
 
-        Chain::hex_to_string(&res[..])
+        let genesis = self.chain.first().unwrap().header.timestamp;
+        let tip = self.chain.last().unwrap().header.timestamp;
+        Some(tip.duration_since(genesis).unwrap_or_default())
     }
 
-    pub fn hex_to_string(vec_res: &[u8]) -> String {
-        let mut s = String::new();
+    // Transfers per second over the last `window` blocks, measured from the block just
+    // before the window to the tip. Miner rewards are not counted.
+    pub fn throughput(&self, window: usize) -> f64 {
+        let window = window.min(self.chain.len().saturating_sub(1));
+        if window == 0 {
+            return 0.0;
+        }
 
-        for b in vec_res {
-            write!(&mut s, "{:x}", b).expect("unable to write")
+        let blocks = &self.chain[self.chain.len() - window - 1..];
+        let elapsed = blocks[window]
+            .header
+            .timestamp
+            .duration_since(blocks[0].header.timestamp)
+            .unwrap_or_default()
+            .as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
         }
 
-        s
+        let transfers: usize = blocks[1..]
+            .iter()
+            .map(|block| block.transactions.len().saturating_sub(1))
+            .sum();
+        transfers as f64 / elapsed
     }
 
-    // TODO: separate in two different functions (VALIDATE & TRANSFER)
-    pub fn check_transfer_availability(
-        &mut self,
-        sender: &String,
-        receiver: &String,
-        amount: f32,
-    ) -> bool {
-        // Check if sender exists and has sufficient balance
-        match self.records.get(sender) {
-            Some(val) => {
-                if val.clone() < amount {
-                    println!("insufficient balance");
-                    return false;
-                }
-            }
-            None => println!("Sender not found!"),
+    // Heights of blocks mined more than `threshold` away from the average block interval,
+    // which points at stalls or manipulated timestamps
+    pub fn anomalous_intervals(&self, threshold: Duration) -> Vec<usize> {
+        let intervals = self.block_intervals();
+        if intervals.is_empty() {
+            return Vec::new();
         }
 
-        // Remove the amount from sender current balance
-        *self.records.get_mut(sender).unwrap() -= amount;
+        let average = intervals.iter().sum::<Duration>() / intervals.len() as u32;
 
-        // Add value in the receiver address
-        match self.records.get_mut(receiver) {
-            Some(_val) => {
-                *self.records.get_mut(receiver).unwrap() += amount;
-            }
-            None => {
-                self.records.insert(receiver.to_string(), amount);
-            }
-        }
-        true
+        intervals
+            .iter()
+            .enumerate()
+            .filter(|(_, interval)| interval.abs_diff(average) > threshold)
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    // Per address change between two balance snapshots, sorted by address.
+    // Addresses missing from a snapshot count as a zero balance.
+    pub fn balance_diff(
+        before: &HashMap<String, f32>,
+        after: &HashMap<String, f32>,
+    ) -> Vec<(String, f32)> {
+        let mut addresses: Vec<&String> = before.keys().chain(after.keys()).collect();
+        addresses.sort();
+        addresses.dedup();
+
+        addresses
+            .into_iter()
+            .map(|address| {
+                let old = before.get(address).cloned().unwrap_or(0.0);
+                let new = after.get(address).cloned().unwrap_or(0.0);
+                (address.clone(), new - old)
+            })
+            .filter(|(_, delta)| *delta != 0.0)
+            .collect()
+    }
+
+    // Median timestamp of the last `window` blocks, as time since the unix epoch
+    pub fn median_time_past(&self, window: usize) -> Option<Duration> {
+        Chain::median_time(&self.chain, window)
+    }
+
+    fn median_time(blocks: &[Block], window: usize) -> Option<Duration> {
+        let start = blocks.len().saturating_sub(window);
+        let mut timestamps: Vec<Duration> = blocks[start..]
+            .iter()
+            .map(|block| Chain::since_epoch(block.header.timestamp))
+            .collect();
+
+        if timestamps.is_empty() {
+            return None;
+        }
+
+        timestamps.sort();
+        Some(timestamps[timestamps.len() / 2])
+    }
+
+    fn since_epoch(timestamp: SystemTime) -> Duration {
+        timestamp.duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+
+    // Each unit of difficulty is one leading hex digit that must be zero
+    pub fn expected_hashes_per_block(&self) -> f64 {
+        16f64.powi(self.difficulty as i32)
+    }
+
+    // Transactions of the blocks with a timestamp in [start, end), with the block height
+    pub fn transactions_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Vec<(usize, &Transaction)> {
+        self.chain
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.header.timestamp >= start && block.header.timestamp < end)
+            .flat_map(|(height, block)| block.transactions.iter().map(move |t| (height, t)))
+            .collect()
+    }
+
+    // Miner reward of every block, genesis included, with the block height
+    pub fn coinbases(&self) -> Vec<(usize, &Transaction)> {
+        self.chain
+            .iter()
+            .enumerate()
+            .filter_map(|(height, block)| block.transactions.first().map(|t| (height, t)))
+            .collect()
+    }
+
+    // True when no transaction of the block has this id (or there is no such block).
+    // A plain scan, not a cryptographic non-membership proof.
+    pub fn transaction_absent(&self, block_index: usize, tx_id: &str) -> bool {
+        match self.chain.get(block_index) {
+            Some(block) => block.transactions.iter().all(|t| t.id() != tx_id),
+            None => true,
+        }
+    }
+
+    // How many block rewards each address received, genesis included
+    pub fn blocks_mined_per_address(&self) -> HashMap<String, usize> {
+        let mut mined = HashMap::new();
+
+        for (_, coinbase) in self.coinbases() {
+            *mined.entry(coinbase.receiver.clone()).or_insert(0) += 1;
+        }
+
+        mined
+    }
+
+    // True when the address received at least one block reward
+    pub fn is_miner(&self, addr: &str) -> bool {
+        self.coinbases()
+            .iter()
+            .any(|(_, coinbase)| coinbase.receiver == addr)
+    }
+
+    // Senders and receivers of a block, so light clients can skip blocks that
+    // certainly don't touch their addresses
+    pub fn address_bloom(&self, block_index: usize) -> Option<BloomFilter> {
+        let block = self.chain.get(block_index)?;
+        let mut filter = BloomFilter::new(block.transactions.len() * 2);
+
+        for transaction in &block.transactions {
+            filter.insert(&transaction.sender);
+            filter.insert(&transaction.receiver);
+        }
+
+        Some(filter)
+    }
+
+    // Net balance change the block applied to the address (received minus sent,
+    // miner reward included), 0 when there is no such block
+    pub fn block_delta_for(&self, block_index: usize, addr: &str) -> f32 {
+        let block = match self.chain.get(block_index) {
+            Some(block) => block,
+            None => return 0.0,
+        };
+
+        block.transactions.iter().fold(0.0, |delta, t| {
+            let mut delta = delta;
+            if t.receiver == addr {
+                delta += t.amount;
+            }
+            if t.sender == addr {
+                delta -= t.amount;
+            }
+            delta
+        })
+    }
+
+    // Height of the first block paying the address, miner reward or transfer
+    pub fn first_funding(&self, addr: &str) -> Option<usize> {
+        self.chain
+            .iter()
+            .position(|block| block.transactions.iter().any(|t| t.receiver == addr))
+    }
+
+    // Expected hashes needed to re-mine every block from `from_height` up to the tip
+    pub fn rewrite_cost(&self, from_height: usize) -> u128 {
+        self.chain
+            .iter()
+            .skip(from_height)
+            .map(|block| Chain::block_work(block.header.difficulty))
+            .fold(0u128, |total, work| total.saturating_add(work))
+    }
+
+    // Same measure as expected_hashes_per_block, one hex digit per difficulty unit
+    fn block_work(difficulty: u32) -> u128 {
+        16u128.saturating_pow(difficulty)
+    }
+
+    pub fn security_warning(&self) -> Option<String> {
+        if self.difficulty >= self.config.min_safe_difficulty {
+            return None;
+        }
+
+        Some(format!(
+            "difficulty {} needs only about {} hashes per block, anyone can rewrite this chain",
+            self.difficulty,
+            self.expected_hashes_per_block()
+        ))
+    }
+
+    pub fn update_difficulty(&mut self, difficulty: u32) -> bool {
+        if difficulty < self.config.min_difficulty || difficulty > MAX_DIFFICULTY {
+            println!(
+                "Difficulty must be between {} and {}",
+                self.config.min_difficulty, MAX_DIFFICULTY
+            );
+            return false;
+        }
+        self.difficulty = difficulty;
+        true
+    }
+
+    pub fn update_reward(&mut self, reward: f32) -> bool {
+        // A block paying no reward, or a NaN one, does not pass check_contents
+        if !reward.is_finite() || reward <= 0.0 {
+            println!("Reward must be a positive number");
+            return false;
+        }
+        self.reward = reward;
+        true
+    }
+
+    // Blocks already mined are not checked against the new limit
+    pub fn update_max_block_bytes(&mut self, max_block_bytes: Option<usize>) -> bool {
+        self.config.max_block_bytes = max_block_bytes;
+        true
+    }
+
+    pub fn generate_new_block(&mut self) -> bool {
+        self.mine_block(SystemTime::now(), String::new(), self.miner_address.clone())
+    }
+
+    // Queues transactions arriving on `incoming` and mines a block each time the batch
+    // window closes, until `stop` is set or every sender has hung up. Whatever is still
+    // pending when the senders are gone is mined in a last block.
+    pub fn auto_mine_loop(
+        &mut self,
+        window: BatchWindow,
+        incoming: &Receiver<Transaction>,
+        stop: &AtomicBool,
+    ) {
+        self.auto_mine_loop_with_clock(window, incoming, stop, &Instant::now);
+    }
+
+    // auto_mine_loop reading the time from `clock`, once per poll of `incoming`
+    pub fn auto_mine_loop_with_clock(
+        &mut self,
+        window: BatchWindow,
+        incoming: &Receiver<Transaction>,
+        stop: &AtomicBool,
+        clock: &dyn Fn() -> Instant,
+    ) {
+        let poll = Duration::from_millis(10);
+        let mut batch_start: Option<Instant> = None;
+
+        while !stop.load(Ordering::Relaxed) {
+            let received = incoming.recv_timeout(poll);
+            let now = clock();
+
+            match received {
+                Ok(tx) => {
+                    if self.new_transaction(tx.sender, tx.receiver, tx.amount) {
+                        batch_start.get_or_insert(now);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    if !self.current_transaction.is_empty() {
+                        self.generate_new_block();
+                    }
+                    return;
+                }
+            }
+
+            let closed = match (window, batch_start) {
+                (_, None) => false,
+                (BatchWindow::Count(count), Some(_)) => self.current_transaction.len() >= count,
+                (BatchWindow::Time(length), Some(start)) => now.duration_since(start) >= length,
+            };
+
+            if closed {
+                self.generate_new_block();
+                batch_start = None;
+            }
+        }
+    }
+
+    // Mines the next block paying the reward to `reward_address` instead of the
+    // default miner address, only for this block
+    pub fn mine_to(&mut self, reward_address: String) -> Result<&Block, MineError> {
+        if !self.mine_block(SystemTime::now(), String::new(), reward_address) {
+            return Err(MineError::TooLarge);
+        }
+        Ok(self.chain.last().unwrap())
+    }
+
+    fn mine_block(
+        &mut self,
+        timestamp: SystemTime,
+        message: String,
+        reward_address: String,
+    ) -> bool {
+        let mut block = self.candidate_block(timestamp, message, reward_address.clone());
+
+        // All Block transactions, or as many as fit in max_block_bytes. The rest stay
+        // pending for the next block.
+        let taken = match self.fitting_transactions(&block) {
+            Some(taken) => taken,
+            None => {
+                println!("Even an empty block is larger than the maximum block size");
+                return false;
+            }
+        };
+        block
+            .transactions
+            .extend(self.current_transaction.drain(..taken));
+        block.count = block.transactions.len() as u32;
+        block.header.merkle_root = Chain::get_merkle(block.transactions.clone());
+        let hash = self.pow.solve(&mut block.header);
+
+        // Add mined coins to the receiver address
+        let receiver = &reward_address;
+        match self.records.get_mut(receiver) {
+            Some(_val) => {
+                *self.records.get_mut(receiver).unwrap() += self.reward;
+                println!("Added {} coins to address {}", self.reward, receiver);
+            }
+            None => {
+                self.records.insert(receiver.to_string(), self.reward);
+                println!("Added {} coins to address {}", self.reward, receiver);
+            }
+        }
+
+        println!("{:#?}", &block);
+        self.chain.push(block);
+        self.tip_hash = hash;
+        true
+    }
+
+    // Unsealed next block holding only the miner reward
+    fn candidate_block(
+        &self,
+        timestamp: SystemTime,
+        message: String,
+        reward_address: String,
+    ) -> Block {
+        // A block at or before the median time past would fail validate_block, which
+        // happens after a genesis timestamp in the future or a clock going backwards
+        let timestamp = match self.median_time_past(self.config.median_time_window) {
+            Some(median) if Chain::since_epoch(timestamp) <= median => {
+                UNIX_EPOCH + median + Duration::from_nanos(1)
+            }
+            _ => timestamp,
+        };
+
+        let header = Header {
+            timestamp,
+            nonce: 0,
+            merkle_root: String::new(),
+            pre_hash: self.last_hash(),
+            difficulty: self.difficulty,
+            message,
+        };
+
+        // Miner reward
+        Block {
+            header,
+            count: 1,
+            transactions: vec![self.reward_transaction(reward_address)],
+        }
+    }
+
+    // How many pending transactions, in order, fit in `block` under max_block_bytes,
+    // None when the block is too large as is. Sizes are measured with the largest
+    // nonce, a full length merkle root and the widest sub-second part of the timestamp,
+    // so neither sealing the block nor mining it a moment after a preview can push it
+    // over.
+    fn fitting_transactions(&self, block: &Block) -> Option<usize> {
+        let max = match self.config.max_block_bytes {
+            Some(max) => max,
+            None => return Some(self.current_transaction.len()),
+        };
+
+        let mut block = block.clone();
+        let secs = Chain::since_epoch(block.header.timestamp).as_secs();
+        block.header.timestamp = UNIX_EPOCH + Duration::new(secs, 999_999_999);
+        block.header.nonce = u32::MAX;
+        block.header.merkle_root = "0".repeat(64);
+
+        let size = |block: &Block| serde_json::to_vec(block).unwrap().len();
+        if size(&block) > max {
+            return None;
+        }
+
+        let mut taken = 0;
+        for transaction in &self.current_transaction {
+            block.transactions.push(transaction.clone());
+            block.count += 1;
+            if size(&block) > max {
+                break;
+            }
+            taken += 1;
+        }
+
+        Some(taken)
+    }
+
+    // Removes every block above `height` and puts their transfers back in the mempool,
+    // ahead of the transactions already pending. Height 0 is genesis, which always stays.
+    pub fn truncate_to(&mut self, height: usize) -> Result<(), ChainError> {
+        if height >= self.chain.len() {
+            return Err(ChainError::HeightOutOfRange);
+        }
+
+        let mut returned: Vec<Transaction> = Vec::new();
+        for block in self.chain.drain(height + 1..) {
+            returned.extend(block.transactions.into_iter().skip(1));
+        }
+        self.rehash_tip();
+        returned.append(&mut self.current_transaction);
+
+        // Rebuild the ledger from the blocks left and queue the transfers again. One
+        // that spent a removed miner reward no longer validates and is dropped.
+        self.records = self.confirmed_balances();
+        self.import_mempool(returned);
+        Ok(())
+    }
+
+    fn reward_transaction(&self, receiver: String) -> Transaction {
+        Transaction {
+            sender: String::from("Root"),
+            receiver,
+            amount: self.reward,
+        }
+    }
+
+    // What generate_new_block would put in the next block, without mining it. When not
+    // even the miner reward fits in max_block_bytes, nothing would be mined and the
+    // preview has no transactions.
+    pub fn preview_next_block(&self) -> BlockPreview {
+        let block =
+            self.candidate_block(SystemTime::now(), String::new(), self.miner_address.clone());
+        let taken = self.fitting_transactions(&block).unwrap_or(0);
+
+        let mut transactions = block.transactions;
+        transactions.extend(self.current_transaction[..taken].iter().cloned());
+
+        BlockPreview {
+            merkle_root: Chain::get_merkle(transactions.clone()),
+            transactions: self.current_transaction[..taken].to_vec(),
+            reward: self.reward,
+        }
+    }
+
+    // One JSON block per line, genesis first
+    pub fn write_jsonl<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        for block in &self.chain {
+            serde_json::to_writer(&mut *w, block)?;
+            w.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    // Migration for chains built with an older merkle algorithm: rewrites every
+    // merkle_root from the block transactions. The root is part of the header, so
+    // each block is also re-linked to its rewritten predecessor and mined again,
+    // which means every block hash changes after the migration.
+    pub fn recompute_merkle_roots(&mut self) {
+        let mut pre_hash = Chain::zero_hash();
+
+        for block in self.chain.iter_mut() {
+            block.header.merkle_root = Chain::get_merkle(block.transactions.clone());
+            block.header.pre_hash = pre_hash;
+            block.header.nonce = 0;
+            pre_hash = self.pow.solve(&mut block.header);
+        }
+        self.tip_hash = pre_hash;
+    }
+
+    // Checks a block as the next block on top of the current chain: it must link to
+    // the tip, carry a valid merkle root and proof of work at this chain's difficulty,
+    // pay this chain's reward, and pass check_contents. `strict` is for blocks this
+    // node mined itself: the reward must then also be paid to our own miner address.
+    pub fn validate_block(&self, block: &Block, strict: bool) -> Result<(), BlockError> {
+        self.check_size(block)?;
+
+        // The proof of work is checked against the header difficulty, which the block
+        // sets itself, so it has to be the one this chain asks for
+        if block.header.difficulty != self.difficulty {
+            return Err(BlockError::WrongDifficulty);
+        }
+
+        if let Some(coinbase) = block.transactions.first() {
+            if coinbase.amount != self.reward {
+                return Err(BlockError::InvalidCoinbase);
+            }
+
+            if strict && coinbase.receiver != self.miner_address {
+                return Err(BlockError::CoinbaseReceiverMismatch);
+            }
+        }
+
+        if block.header.pre_hash != self.last_hash() {
+            return Err(BlockError::BrokenLink);
+        }
+
+        self.check_seal(block)?;
+        let mut records = self.confirmed_balances();
+        self.check_contents(&self.chain, block, &mut records)
+    }
+
+    // Rules for what a block carries, whatever difficulty and reward were set when it
+    // was mined: be newer than the median time past of the `prior` blocks, start with
+    // the single "Root" reward transaction, and only spend funded balances. The
+    // transfers are applied in order to `records`, the balances after `prior`, so
+    // several transfers from one sender cannot together spend more than it has. Each
+    // transfer gets the same checks as a new transaction.
+    fn check_contents(
+        &self,
+        prior: &[Block],
+        block: &Block,
+        records: &mut HashMap<String, f32>,
+    ) -> Result<(), BlockError> {
+        if let Some(median) = Chain::median_time(prior, self.config.median_time_window) {
+            if Chain::since_epoch(block.header.timestamp) <= median {
+                return Err(BlockError::TimestampTooOld);
+            }
+        }
+
+        let coinbase = match block.transactions.first() {
+            Some(transaction) => transaction,
+            None => return Err(BlockError::InvalidCoinbase),
+        };
+
+        if coinbase.sender != "Root"
+            || !coinbase.amount.is_finite()
+            || coinbase.amount <= 0.0
+            || block.transactions[1..].iter().any(|t| t.sender == "Root")
+        {
+            return Err(BlockError::InvalidCoinbase);
+        }
+        *records.entry(coinbase.receiver.clone()).or_insert(0.0) += coinbase.amount;
+
+        for transaction in &block.transactions[1..] {
+            let (sender, receiver) = (&transaction.sender, &transaction.receiver);
+            match self.validate_transfer(records, sender, receiver, transaction.amount) {
+                Ok(()) => Chain::transfer(records, sender, receiver, transaction.amount),
+                Err(TransactionError::InsufficientBalance) => return Err(BlockError::Overdraw),
+                Err(err) => return Err(BlockError::InvalidTransaction(err)),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Balances from the mined blocks alone. records also already includes the
+    // pending transactions, which a candidate block must not build on.
+    fn confirmed_balances(&self) -> HashMap<String, f32> {
+        self.fold_transactions(HashMap::new(), |mut records, _, transaction| {
+            if transaction.sender != "Root" {
+                *records.entry(transaction.sender.clone()).or_insert(0.0) -= transaction.amount;
+            }
+            *records.entry(transaction.receiver.clone()).or_insert(0.0) += transaction.amount;
+            records
+        })
+    }
+
+    // Checks a run of blocks without the chain they belong to: the first block must
+    // point at `anchor_hash`, each next one at its predecessor, and all of them need
+    // a valid merkle root and proof of work at no less than min_difficulty.
+    pub fn verify_segment(&self, anchor_hash: &str, segment: &[Block]) -> Result<(), SegmentError> {
+        let mut pre_hash = anchor_hash.to_string();
+
+        for (index, block) in segment.iter().enumerate() {
+            let result = if let Err(error) = self.check_size(block) {
+                Err(error)
+            } else if block.header.pre_hash != pre_hash {
+                Err(BlockError::BrokenLink)
+            } else if block.header.difficulty < self.config.min_difficulty {
+                Err(BlockError::WrongDifficulty)
+            } else {
+                self.check_seal(block)
+            };
+
+            if let Err(error) = result {
+                return Err(SegmentError { index, error });
+            }
+
+            pre_hash = Chain::hash(&block.header);
+        }
+
+        Ok(())
+    }
+
+    // Checks a complete chain received from elsewhere, from genesis on: verify_segment
+    // from the zero hash, then every block replayed through check_contents on a running
+    // ledger. With a work checkpoint configured, the chain must also reach the
+    // checkpoint height with exactly the trusted cumulative work.
+    pub fn validate_chain(&self, blocks: &[Block]) -> Result<(), ChainError> {
+        if let Some((height, work)) = self.config.work_checkpoint {
+            if blocks.len() <= height {
+                return Err(ChainError::CheckpointMismatch);
+            }
+
+            let cumulative = blocks[..=height]
+                .iter()
+                .map(|block| Chain::block_work(block.header.difficulty))
+                .fold(0u128, |total, work| total.saturating_add(work));
+
+            if cumulative != work {
+                return Err(ChainError::CheckpointMismatch);
+            }
+        }
+
+        self.verify_segment(&Chain::zero_hash(), blocks)
+            .map_err(ChainError::InvalidBlock)?;
+
+        let mut records = HashMap::new();
+        for (index, block) in blocks.iter().enumerate() {
+            self.check_contents(&blocks[..index], block, &mut records)
+                .map_err(|error| ChainError::InvalidBlock(SegmentError { index, error }))?;
+        }
+
+        Ok(())
+    }
+
+    // Cheap rejection of oversized blocks before hashing anything
+    fn check_size(&self, block: &Block) -> Result<(), BlockError> {
+        match self.config.max_block_bytes {
+            Some(max) if serde_json::to_vec(block).unwrap().len() > max => {
+                Err(BlockError::TooLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Merkle root and proof of work, the checks that need nothing but the block
+    fn check_seal(&self, block: &Block) -> Result<(), BlockError> {
+        if block.transactions.is_empty()
+            || block.header.merkle_root != Chain::get_merkle(block.transactions.clone())
+        {
+            return Err(BlockError::InvalidMerkleRoot);
+        }
+
+        if !self.pow.verify(&block.header) {
+            return Err(BlockError::InvalidProofOfWork);
+        }
+
+        Ok(())
+    }
+
+    fn get_merkle(current_transactions: Vec<Transaction>) -> String {
+        let mut merkle = Vec::new();
+
+        for transaction in &current_transactions {
+            let hash = Chain::hash(transaction);
+            merkle.push(hash);
+        }
+
+        if merkle.len() % 2 == 1 {
+            let last = merkle.last().cloned().unwrap();
+            merkle.push(last);
+        }
+
+        while merkle.len() > 1 {
+            // Get the next two (first) hashes
+            let mut hash1 = merkle.remove(0);
+            let hash2 = merkle.remove(0);
+
+            // Creates a hash based on the two previous hashes
+            hash1.push_str(&hash2);
+            let merged_hash = Chain::hash(&hash1);
+
+            // Put it back on the merkle_root vector
+            merkle.push(merged_hash);
+        }
+
+        merkle.pop().unwrap()
+    }
+
+    // Sets the header nonce and returns the resulting block hash. When every nonce
+    // after the starting one fails, the timestamp moves one second forward and the
+    // search starts over from nonce 0.
+    // yield_interval trades mining speed for a responsive system on a single core
+    pub fn proof_of_work(header: &mut Header, yield_interval: u32) -> String {
+        let mut attempts: u32 = 0;
+
+        loop {
+            // Only the winning digest is turned into a hex string
+            let digest = Chain::hash_bytes(header);
+            if Chain::meets_difficulty(&digest, header.difficulty) {
+                let hash = Chain::hex_to_string(&digest);
+                println!("Block hash: {}", hash);
+                return hash;
+            }
+            header.nonce = match header.nonce.checked_add(1) {
+                Some(nonce) => nonce,
+                None => {
+                    header.timestamp += Duration::from_secs(1);
+                    0
+                }
+            };
+
+            attempts = attempts.wrapping_add(1);
+            if yield_interval > 0 && attempts.is_multiple_of(yield_interval) {
+                thread::yield_now();
+            }
+        }
+    }
+
+    // The first `difficulty` hex digits of the hash must be zero, checked on the raw
+    // digest: two digits per byte, the high nibble first
+    fn meets_difficulty(digest: &[u8; 32], difficulty: u32) -> bool {
+        let difficulty = difficulty as usize;
+        if difficulty > digest.len() * 2 {
+            return false;
+        }
+
+        let full_bytes = difficulty / 2;
+        digest[..full_bytes].iter().all(|byte| *byte == 0)
+            && (difficulty.is_multiple_of(2) || digest[full_bytes] >> 4 == 0)
+    }
+
+    // Generic T here will be a type of serde.Serialize
+    pub fn hash<T: serde::Serialize>(item: &T) -> String {
+        Chain::hex_to_string(&Chain::hash_bytes(item))
+    }
+
+    // Raw sha256 digest behind Chain::hash
+    fn hash_bytes<T: serde::Serialize>(item: &T) -> [u8; 32] {
+        let input = serde_json::to_string(&item).unwrap();
+        let mut hasher = Sha256::default();
+
+        hasher.update(input.as_bytes());
+        hasher.finalize().into()
+    }
+
+    // Work implied by the header hash: the smaller the hash, the more attempts it
+    // took on average to find it. Uses the top 128 bits of the digest, which is
+    // plenty to rank competing headers.
+    pub fn header_work(header: &Header) -> u128 {
+        let digest = Chain::hash_bytes(header);
+        let mut top = [0u8; 16];
+        top.copy_from_slice(&digest[..16]);
+
+        u128::MAX / (u128::from_be_bytes(top).saturating_add(1))
+    }
+
+    pub fn hex_to_string(vec_res: &[u8]) -> String {
+        let mut s = String::new();
+
+        for b in vec_res {
+            write!(&mut s, "{:02x}", b).expect("unable to write")
+        }
+
+        s
+    }
+
+    pub fn check_transfer_availability(
+        &mut self,
+        sender: &str,
+        receiver: &str,
+        amount: f32,
+    ) -> bool {
+        if let Err(err) = self.validate_transfer(&self.records, sender, receiver, amount) {
+            println!("{}", err);
+            return false;
+        }
+
+        Chain::transfer(&mut self.records, sender, receiver, amount);
+        true
+    }
+
+    // Check if sender exists and has sufficient balance
+    fn validate_transfer(
+        &self,
+        records: &HashMap<String, f32>,
+        sender: &str,
+        receiver: &str,
+        amount: f32,
+    ) -> Result<(), TransactionError> {
+        // NaN compares false against everything, so it must be caught before the
+        // balance checks
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(TransactionError::InvalidAmount);
+        }
+
+        if amount < self.config.dust_threshold {
+            return Err(TransactionError::DustAmount);
+        }
+
+        match records.get(sender) {
+            Some(val) if *val < amount => return Err(TransactionError::InsufficientBalance),
+            Some(_) => {}
+            None => return Err(TransactionError::UnknownSender),
+        }
+
+        if self.config.require_known_receiver && !records.contains_key(receiver) {
+            return Err(TransactionError::UnknownReceiver);
+        }
+
+        Ok(())
+    }
+
+    // Moves the amount from sender to receiver, the transfer must be validated first
+    fn transfer(records: &mut HashMap<String, f32>, sender: &str, receiver: &str, amount: f32) {
+        *records.get_mut(sender).unwrap() -= amount;
+        *records.entry(receiver.to_string()).or_insert(0.0) += amount;
+    }
+}
+
+// Number of decimal places shown for amounts counted in smallest units
+pub const DECIMALS: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseAmountError {
+    Empty,
+    InvalidDigit,
+    TooPrecise,
+    Overflow,
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseAmountError::Empty => write!(f, "empty amount"),
+            ParseAmountError::InvalidDigit => write!(f, "invalid digit in amount"),
+            ParseAmountError::TooPrecise => write!(f, "more than {} decimal places", DECIMALS),
+            ParseAmountError::Overflow => write!(f, "amount too large"),
+        }
+    }
+}
+
+// 150000000 units -> "1.50000000"
+pub fn format_amount(units: u64) -> String {
+    let scale = 10u64.pow(DECIMALS);
+    format!(
+        "{}.{:0width$}",
+        units / scale,
+        units % scale,
+        width = DECIMALS as usize
+    )
+}
+
+// "1.5" -> 150000000 units, inputs with more than DECIMALS places are rejected
+pub fn parse_amount(s: &str) -> Result<u64, ParseAmountError> {
+    let s = s.trim();
+    let (whole, frac) = match s.split_once('.') {
+        Some((whole, frac)) => (whole, frac),
+        None => (s, ""),
+    };
+
+    if whole.is_empty() && frac.is_empty() {
+        return Err(ParseAmountError::Empty);
+    }
+
+    let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if !all_digits(whole) || !all_digits(frac) {
+        return Err(ParseAmountError::InvalidDigit);
+    }
+
+    if frac.len() > DECIMALS as usize {
+        return Err(ParseAmountError::TooPrecise);
+    }
+
+    // Shift the fraction digits up to the smallest unit, e.g. "5" -> "50000000"
+    let mut units = String::from(whole);
+    units.push_str(frac);
+    units.push_str(&"0".repeat(DECIMALS as usize - frac.len()));
+
+    units.parse().map_err(|_| ParseAmountError::Overflow)
+}
+
+// Golden vectors: fixed inputs and the hashes they produced when the format was
+// last changed on purpose. A mismatch means serialization or hashing drifted,
+// e.g. after a serde upgrade, and every stored hash would change with it.
+const GOLDEN_TRANSACTION_HASH: &str =
+    "8f6a92d7e2f087f57a494d3dcaff346479bea312f487524e7d6e826bf58ee3b6";
+const GOLDEN_MERKLE_ROOT: &str = "16adb9c508720b964eb72baddb9f839a6607efaf0c7ef21533c25ddf64cb0615";
+const GOLDEN_HEADER_HASH: &str = "1c6467d8c52aad9b7f0645abdc9252949182d4b2ed2861c4c535b5c2cf193d19";
+
+fn golden_transactions() -> Vec<Transaction> {
+    vec![
+        Transaction::new(String::from("Root"), String::from("alice"), 100.0),
+        Transaction::new(String::from("alice"), String::from("bob"), 1.5),
+        Transaction::new(String::from("bob"), String::from("carol"), 0.25),
+    ]
+}
+
+fn golden_header() -> Header {
+    Header {
+        timestamp: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        nonce: 42,
+        pre_hash: Chain::zero_hash(),
+        merkle_root: String::from(GOLDEN_MERKLE_ROOT),
+        difficulty: 2,
+        message: String::from("golden"),
+    }
+}
+
+// Recomputes the golden vectors, Err names the first one that no longer matches
+pub fn self_test() -> Result<(), String> {
+    let transactions = golden_transactions();
+
+    let checks = [
+        (
+            "transaction hash",
+            Chain::hash(&transactions[1]),
+            GOLDEN_TRANSACTION_HASH,
+        ),
+        (
+            "merkle root",
+            Chain::get_merkle(transactions),
+            GOLDEN_MERKLE_ROOT,
+        ),
+        (
+            "header hash",
+            Chain::hash(&golden_header()),
+            GOLDEN_HEADER_HASH,
+        ),
+    ];
+
+    for (name, actual, expected) in checks.iter() {
+        if actual != expected {
+            return Err(format!("{}: expected {}, got {}", name, expected, actual));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::mpsc;
+
+    fn test_chain() -> Chain {
+        Chain::new(String::from("miner"), 1)
+    }
+
+    #[test]
+    fn fold_transactions_sums_volume_between_two_addresses() {
+        let mut chain = test_chain();
+        chain.new_transaction(String::from("miner"), String::from("alice"), 30.0);
+        chain.generate_new_block();
+        chain.new_transaction(String::from("alice"), String::from("miner"), 5.0);
+        chain.new_transaction(String::from("miner"), String::from("bob"), 7.0);
+        chain.generate_new_block();
+
+        let between = |t: &Transaction| {
+            (t.sender == "miner" && t.receiver == "alice")
+                || (t.sender == "alice" && t.receiver == "miner")
+        };
+
+        let folded =
+            chain.fold_transactions(
+                0.0,
+                |total, _, t| {
+                    if between(t) {
+                        total + t.amount
+                    } else {
+                        total
+                    }
+                },
+            );
+
+        let mut manual = 0.0;
+        for block in &chain.chain {
+            for t in &block.transactions {
+                if between(t) {
+                    manual += t.amount;
+                }
+            }
+        }
+
+        assert_eq!(folded, manual);
+        assert_eq!(folded, 35.0);
+    }
+
+    #[test]
+    fn recompute_merkle_roots_repairs_legacy_roots() {
+        let mut chain = test_chain();
+        chain.new_transaction(String::from("miner"), String::from("alice"), 10.0);
+        chain.generate_new_block();
+        chain.generate_new_block();
+
+        for block in chain.chain.iter_mut() {
+            block.header.merkle_root = String::from("legacy");
+        }
+        assert!(chain.validate_chain(&chain.chain).is_err());
+
+        chain.recompute_merkle_roots();
+
+        assert_eq!(chain.validate_chain(&chain.chain), Ok(()));
+    }
+
+    #[test]
+    fn expected_hashes_per_block_grows_by_16_per_difficulty() {
+        let mut chain = test_chain();
+
+        chain.difficulty = 0;
+        assert_eq!(chain.expected_hashes_per_block(), 1.0);
+        chain.difficulty = 1;
+        assert_eq!(chain.expected_hashes_per_block(), 16.0);
+        chain.difficulty = 2;
+        assert_eq!(chain.expected_hashes_per_block(), 256.0);
+    }
+
+    fn genesis_config() -> ChainConfig {
+        ChainConfig {
+            genesis_message: String::from("toy chain genesis"),
+            genesis_timestamp: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..ChainConfig::default()
+        }
+    }
+
+    #[test]
+    fn genesis_carries_configured_message_and_timestamp() {
+        let chain = Chain::with_config(String::from("miner"), 1, genesis_config());
+        let genesis = &chain.chain[0].header;
+
+        assert_eq!(genesis.message, "toy chain genesis");
+        assert_eq!(
+            genesis.timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn same_genesis_config_gives_same_genesis_hash() {
+        let a = Chain::with_config(String::from("miner"), 1, genesis_config());
+        let b = Chain::with_config(String::from("miner"), 1, genesis_config());
+
+        assert_eq!(a.last_hash(), b.last_hash());
+    }
+
+    #[test]
+    fn balances_sorted_is_ordered_by_address() {
+        let mut chain = test_chain();
+        for receiver in ["zed", "alice", "mike", "bob"] {
+            chain.new_transaction(String::from("miner"), String::from(receiver), 1.0);
+        }
+
+        let balances = chain.balances_sorted();
+        let addresses: Vec<&str> = balances.iter().map(|(a, _)| a.as_str()).collect();
+
+        assert_eq!(addresses, vec!["alice", "bob", "mike", "miner", "zed"]);
+    }
+
+    #[test]
+    fn simulate_projects_balances_without_touching_the_ledger() {
+        let chain = test_chain();
+        let txs = vec![
+            Transaction::new(String::from("miner"), String::from("alice"), 40.0),
+            Transaction::new(String::from("alice"), String::from("bob"), 15.0),
+        ];
+
+        let projected = chain.simulate(&txs).unwrap();
+
+        assert_eq!(projected["miner"], 60.0);
+        assert_eq!(projected["alice"], 25.0);
+        assert_eq!(projected["bob"], 15.0);
+        assert_eq!(chain.balance("miner"), Some(100.0));
+        assert_eq!(chain.balance("alice"), None);
+    }
+
+    #[test]
+    fn simulate_fails_on_an_invalid_transaction() {
+        let chain = test_chain();
+        let txs = vec![
+            Transaction::new(String::from("miner"), String::from("alice"), 40.0),
+            Transaction::new(String::from("alice"), String::from("bob"), 50.0),
+        ];
+
+        assert_eq!(
+            chain.simulate(&txs),
+            Err(TransactionError::InsufficientBalance)
+        );
+    }
+
+    #[test]
+    fn non_finite_and_non_positive_amounts_are_rejected() {
+        let mut chain = test_chain();
+
+        for amount in [f32::NAN, f32::INFINITY, 0.0, -10.0] {
+            assert!(!chain.new_transaction(String::from("miner"), String::from("bob"), amount));
+        }
+
+        assert_eq!(chain.balance("miner"), Some(100.0));
+        assert!(chain.current_transaction.is_empty());
+    }
+
+    #[test]
+    fn unknown_receivers_are_accepted_by_default() {
+        let mut chain = test_chain();
+
+        assert!(chain.new_transaction(String::from("miner"), String::from("typo"), 5.0));
+        assert_eq!(chain.balance("typo"), Some(5.0));
+    }
+
+    #[test]
+    fn require_known_receiver_rejects_unknown_addresses() {
+        let config = ChainConfig {
+            require_known_receiver: true,
+            ..ChainConfig::default()
+        };
+        let chain = Chain::with_config(String::from("miner"), 1, config);
+
+        assert_eq!(
+            chain.validate_transfer(&chain.records, "miner", "typo", 5.0),
+            Err(TransactionError::UnknownReceiver)
+        );
+        assert_eq!(
+            chain.validate_transfer(&chain.records, "miner", "miner", 5.0),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn balance_distribution_reports_gini_and_top_share() {
+        let mut chain = test_chain();
+        chain.records = HashMap::from([
+            (String::from("a"), 10.0),
+            (String::from("b"), 20.0),
+            (String::from("c"), 30.0),
+            (String::from("d"), 40.0),
+        ]);
+
+        let stats = chain.balance_distribution();
+
+        assert_eq!(stats.accounts, 4);
+        assert_eq!(stats.total_supply, 100.0);
+        assert!((stats.gini - 0.25).abs() < 1e-6);
+        assert!((stats.top_share - 0.4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn balance_distribution_of_equal_balances_has_zero_gini() {
+        let mut chain = test_chain();
+        chain.records = HashMap::from([(String::from("a"), 50.0), (String::from("b"), 50.0)]);
+
+        let stats = chain.balance_distribution();
+
+        assert!(stats.gini.abs() < 1e-6);
+        assert!((stats.top_share - 0.5).abs() < 1e-6);
+    }
+
+    // Seals the transactions into the next block on top of `chain`, without adding it
+    fn seal(chain: &Chain, transactions: Vec<Transaction>, difficulty: u32) -> Block {
+        let mut header = Header {
+            timestamp: SystemTime::now(),
+            nonce: 0,
+            pre_hash: chain.last_hash(),
+            merkle_root: Chain::get_merkle(transactions.clone()),
+            difficulty,
+            message: String::new(),
+        };
+        chain.pow.solve(&mut header);
+
+        Block {
+            header,
+            count: transactions.len() as u32,
+            transactions,
+        }
+    }
+
+    // Next block paying the usual reward to `reward_address`, followed by `transfers`
+    fn sealed_block(chain: &Chain, reward_address: &str, transfers: Vec<Transaction>) -> Block {
+        let mut transactions = vec![chain.reward_transaction(String::from(reward_address))];
+        transactions.extend(transfers);
+        seal(chain, transactions, chain.difficulty)
+    }
+
+    #[test]
+    fn validate_block_accepts_a_well_formed_block() {
+        let chain = test_chain();
+        let block = sealed_block(&chain, "miner", vec![]);
+
+        assert_eq!(chain.validate_block(&block, true), Ok(()));
+    }
+
+    #[test]
+    fn strict_mode_rejects_a_tampered_coinbase_receiver() {
+        let chain = test_chain();
+        let mut block = sealed_block(&chain, "miner", vec![]);
+        block.transactions[0].receiver = String::from("thief");
+        block.header.merkle_root = Chain::get_merkle(block.transactions.clone());
+        chain.pow.solve(&mut block.header);
+
+        assert_eq!(
+            chain.validate_block(&block, true),
+            Err(BlockError::CoinbaseReceiverMismatch)
+        );
+        // A received block may pay any miner
+        assert_eq!(chain.validate_block(&block, false), Ok(()));
+    }
+
+    #[test]
+    fn validate_block_rejects_a_lower_self_declared_difficulty() {
+        let chain = Chain::new(String::from("miner"), 3);
+        let transactions = vec![chain.reward_transaction(String::from("miner"))];
+        let block = seal(&chain, transactions, 0);
+
+        assert_eq!(
+            chain.validate_block(&block, false),
+            Err(BlockError::WrongDifficulty)
+        );
+    }
+
+    #[test]
+    fn exported_mempool_imports_into_a_fresh_chain_and_mines() {
+        let config = ChainConfig {
+            genesis_timestamp: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..ChainConfig::default()
+        };
+        let mut source = Chain::with_config(String::from("miner"), 1, config.clone());
+        source.new_transaction(String::from("miner"), String::from("alice"), 20.0);
+        source.new_transaction(String::from("alice"), String::from("bob"), 5.0);
+
+        let mempool = source.export_mempool();
+        assert_eq!(mempool.len(), 2);
+
+        let mut target = Chain::with_config(String::from("miner"), 1, config);
+        assert_eq!(target.import_mempool(mempool), 2);
+        target.generate_new_block();
+
+        let block = target.chain.last().unwrap();
+        assert_eq!(block.transactions.len(), 3);
+        assert!(target.current_transaction.is_empty());
+        assert_eq!(target.balance("alice"), Some(15.0));
+        assert_eq!(target.balance("bob"), Some(5.0));
+    }
+
+    #[test]
+    fn amounts_round_trip_through_format_and_parse() {
+        assert_eq!(parse_amount("1.5"), Ok(150_000_000));
+        assert_eq!(format_amount(150_000_000), "1.50000000");
+        assert_eq!(parse_amount("0.00000001"), Ok(1));
+        assert_eq!(format_amount(1), "0.00000001");
+        assert_eq!(parse_amount("42"), Ok(4_200_000_000));
+
+        for units in [0, 1, 150_000_000, 123_456_789_012, u64::MAX] {
+            assert_eq!(parse_amount(&format_amount(units)), Ok(units));
+        }
+    }
+
+    #[test]
+    fn parse_amount_rejects_malformed_input() {
+        assert_eq!(
+            parse_amount("0.000000001"),
+            Err(ParseAmountError::TooPrecise)
+        );
+        assert_eq!(parse_amount(""), Err(ParseAmountError::Empty));
+        assert_eq!(parse_amount("1.2.3"), Err(ParseAmountError::InvalidDigit));
+        assert_eq!(parse_amount("-1"), Err(ParseAmountError::InvalidDigit));
+        assert_eq!(
+            parse_amount("184467440737.09551616"),
+            Err(ParseAmountError::Overflow)
+        );
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    // Chain whose blocks carry the given timestamps, genesis included
+    fn chain_mined_at(times: &[u64]) -> Chain {
+        let config = ChainConfig {
+            genesis_timestamp: Some(at(times[0])),
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 1, config);
+        for secs in &times[1..] {
+            chain.mine_block(at(*secs), String::new(), String::from("miner"));
+        }
+        chain
+    }
+
+    #[test]
+    fn block_intervals_and_anomalies_follow_the_timestamps() {
+        let chain = chain_mined_at(&[0, 10, 20, 30, 90, 100]);
+
+        let expected: Vec<Duration> = [10, 10, 10, 60, 10]
+            .iter()
+            .map(|secs| Duration::from_secs(*secs))
+            .collect();
+        assert_eq!(chain.block_intervals(), expected);
+
+        // Average interval is 20s, only the 60s stall before block 4 is 25s+ off
+        assert_eq!(chain.anomalous_intervals(Duration::from_secs(25)), vec![4]);
+        assert!(chain
+            .anomalous_intervals(Duration::from_secs(60))
+            .is_empty());
+    }
+
+    #[test]
+    fn truncate_to_matches_a_fresh_replay() {
+        let config = ChainConfig {
+            genesis_timestamp: Some(at(1_700_000_000)),
+            ..ChainConfig::default()
+        };
+        let transfers = |height: u32| {
+            vec![
+                (
+                    String::from("miner"),
+                    String::from("alice"),
+                    10.0 * height as f32,
+                ),
+                (String::from("alice"), String::from("bob"), 1.0),
+            ]
+        };
+
+        let mut chain = Chain::with_config(String::from("miner"), 1, config.clone());
+        for height in 1..=5 {
+            for (sender, receiver, amount) in transfers(height) {
+                assert!(chain.new_transaction(sender, receiver, amount));
+            }
+            chain.generate_new_block();
+        }
+
+        chain.truncate_to(2).unwrap();
+
+        let mut replay = Chain::with_config(String::from("miner"), 1, config);
+        for height in 1..=5 {
+            for (sender, receiver, amount) in transfers(height) {
+                assert!(replay.new_transaction(sender, receiver, amount));
+            }
+            if height <= 2 {
+                replay.generate_new_block();
+            }
+        }
+
+        assert_eq!(chain.chain.len(), 3);
+        assert_eq!(chain.chain.len(), replay.chain.len());
+        assert_eq!(chain.balances_sorted(), replay.balances_sorted());
+        assert_eq!(
+            serde_json::to_value(&chain.current_transaction).unwrap(),
+            serde_json::to_value(&replay.current_transaction).unwrap()
+        );
+    }
+
+    #[test]
+    fn truncate_to_drops_transfers_funded_by_a_removed_reward() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 150.0));
+        chain.generate_new_block();
+
+        chain.truncate_to(0).unwrap();
+
+        assert_eq!(chain.balance("miner"), Some(100.0));
+        assert_eq!(chain.balance("bob"), None);
+        assert!(chain.current_transaction.is_empty());
+    }
+
+    #[test]
+    fn truncate_to_rejects_a_height_above_the_tip() {
+        let mut chain = test_chain();
+
+        assert_eq!(chain.truncate_to(1), Err(ChainError::HeightOutOfRange));
+    }
+
+    // Accepts any header whose nonce is a multiple of 7
+    struct MultipleOfSeven;
+
+    impl ProofOfWork for MultipleOfSeven {
+        fn solve(&self, header: &mut Header) -> String {
+            header.nonce = 42;
+            Chain::hash(header)
+        }
+
+        fn verify(&self, header: &Header) -> bool {
+            header.nonce.is_multiple_of(7)
+        }
+    }
+
+    #[test]
+    fn custom_proof_of_work_mines_and_validates_a_chain() {
+        let mut chain = Chain::with_proof_of_work(
+            String::from("miner"),
+            1,
+            ChainConfig::default(),
+            Box::new(MultipleOfSeven),
+        );
+        chain.generate_new_block();
+        chain.generate_new_block();
+
+        assert!(chain.chain.iter().all(|block| block.header.nonce == 42));
+        assert_eq!(chain.validate_chain(&chain.chain), Ok(()));
+
+        let mut forged = chain.chain.clone();
+        forged[2].header.nonce = 43;
+        assert!(chain.validate_chain(&forged).is_err());
+    }
+
+    #[test]
+    fn first_funding_finds_the_first_paying_block() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+
+        assert_eq!(chain.first_funding("miner"), Some(0));
+        assert_eq!(chain.first_funding("bob"), Some(2));
+        assert_eq!(chain.first_funding("carol"), None);
+    }
+
+    #[test]
+    fn preview_matches_the_mined_block() {
+        for max_block_bytes in [None, Some(500)] {
+            let config = ChainConfig {
+                max_block_bytes,
+                ..ChainConfig::default()
+            };
+            let mut chain = Chain::with_config(String::from("miner"), 1, config);
+            for _ in 0..5 {
+                assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+            }
+            assert!(chain.new_transaction(String::from("bob"), String::from("carol"), 2.0));
+
+            let preview = chain.preview_next_block();
+            let height = chain.height();
+            assert!(chain.generate_new_block());
+
+            let block = chain.block(chain.height()).unwrap();
+            assert_eq!(chain.height(), height + 1);
+            assert_eq!(
+                serde_json::to_value(&preview.transactions).unwrap(),
+                serde_json::to_value(&block.transactions[1..]).unwrap()
+            );
+            assert_eq!(preview.reward, block.transactions[0].amount);
+            assert_eq!(preview.merkle_root, block.header.merkle_root);
+            match max_block_bytes {
+                None => assert_eq!(preview.transactions.len(), 6),
+                Some(_) => assert!(preview.transactions.len() < 6),
+            }
+        }
+    }
+
+    #[test]
+    fn balance_diff_matches_the_effects_of_a_block() {
+        let mut chain = test_chain();
+        let before = chain.confirmed_balances();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 30.0));
+        assert!(chain.new_transaction(String::from("bob"), String::from("carol"), 10.0));
+        chain.generate_new_block();
+        let after = chain.confirmed_balances();
+
+        assert_eq!(
+            Chain::balance_diff(&before, &after),
+            vec![
+                (String::from("bob"), 20.0),
+                (String::from("carol"), 10.0),
+                (String::from("miner"), 70.0),
+            ]
+        );
+        assert!(Chain::balance_diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn mine_to_pays_the_given_address_for_one_block() {
+        let mut chain = test_chain();
+        let reward = chain.reward();
+
+        let block = chain.mine_to(String::from("pool-a")).unwrap();
+        assert_eq!(block.transactions[0].receiver, "pool-a");
+        chain.mine_to(String::from("pool-b")).unwrap();
+
+        assert_eq!(chain.balance("pool-a"), Some(reward));
+        assert_eq!(chain.balance("pool-b"), Some(reward));
+        // Only the genesis reward
+        assert_eq!(chain.balance("miner"), Some(reward));
+        assert_eq!(chain.miner_address, "miner");
+    }
+
+    // sealed_block with the given header timestamp
+    fn sealed_block_at(chain: &Chain, secs: u64) -> Block {
+        let mut block = sealed_block(chain, "miner", vec![]);
+        block.header.timestamp = at(secs);
+        chain.pow.solve(&mut block.header);
+        block
+    }
+
+    #[test]
+    fn validate_block_needs_a_timestamp_after_the_median_time_past() {
+        let chain = chain_mined_at(&[100, 200, 300, 400, 500]);
+
+        assert_eq!(chain.median_time_past(11), Some(Duration::from_secs(300)));
+        assert_eq!(chain.median_time_past(2), Some(Duration::from_secs(500)));
+        assert_eq!(
+            chain.validate_block(&sealed_block_at(&chain, 300), false),
+            Err(BlockError::TimestampTooOld)
+        );
+        assert_eq!(
+            chain.validate_block(&sealed_block_at(&chain, 301), false),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn every_block_has_a_root_coinbase() {
+        let mut chain = test_chain();
+        for _ in 0..3 {
+            chain.generate_new_block();
+        }
+
+        let coinbases = chain.coinbases();
+        // The genesis block carries a coinbase too
+        assert_eq!(coinbases.len(), chain.chain.len());
+        for (i, (height, coinbase)) in coinbases.iter().enumerate() {
+            assert_eq!(*height, i);
+            assert_eq!(coinbase.sender, "Root");
+        }
+    }
+
+    #[test]
+    fn dust_threshold_rejects_smaller_transfers_but_not_the_coinbase() {
+        let config = ChainConfig {
+            dust_threshold: 1.0,
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 1, config);
+        let dust = Transaction::new(String::from("miner"), String::from("bob"), 0.5);
+        let at_threshold = Transaction::new(String::from("miner"), String::from("bob"), 1.0);
+
+        assert_eq!(
+            chain.simulate(&[dust]).err(),
+            Some(TransactionError::DustAmount)
+        );
+        assert!(chain.simulate(&[at_threshold]).is_ok());
+        assert!(!chain.new_transaction(String::from("miner"), String::from("bob"), 0.5));
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 1.0));
+
+        chain.update_reward(0.25);
+        chain.generate_new_block();
+        assert_eq!(chain.block(1).unwrap().transactions[0].amount, 0.25);
+    }
+
+    #[test]
+    fn rewrite_cost_sums_the_work_of_the_suffix() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        chain.update_difficulty(2);
+        chain.generate_new_block();
+        chain.generate_new_block();
+
+        assert_eq!(chain.rewrite_cost(0), 16 + 16 + 256 + 256);
+        assert_eq!(chain.rewrite_cost(2), 256 + 256);
+        assert_eq!(chain.rewrite_cost(4), 0);
+    }
+
+    #[test]
+    fn validators_run_alongside_the_balance_checks() {
+        let mut chain = test_chain();
+        chain.add_validator(|transaction, _| {
+            if transaction.amount > 50.0 {
+                return Err(String::from("amount over the cap"));
+            }
+            Ok(())
+        });
+
+        assert!(!chain.new_transaction(String::from("miner"), String::from("bob"), 60.0));
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 40.0));
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 40.0));
+        // Under the cap, but more than the miner has left
+        assert!(!chain.new_transaction(String::from("miner"), String::from("bob"), 40.0));
+
+        assert_eq!(chain.current_transaction.len(), 2);
+        assert_eq!(chain.balance("bob"), Some(80.0));
+    }
+
+    #[test]
+    fn chain_age_spans_genesis_to_tip() {
+        assert_eq!(chain_mined_at(&[1_000]).chain_age(), None);
+
+        let chain = chain_mined_at(&[1_000, 1_600, 4_600]);
+        assert_eq!(chain.chain_age(), Some(Duration::from_secs(3_600)));
+    }
+
+    fn block_bytes(block: &Block) -> usize {
+        serde_json::to_vec(block).unwrap().len()
+    }
+
+    #[test]
+    fn mining_stops_filling_the_block_at_max_block_bytes() {
+        let config = ChainConfig {
+            max_block_bytes: Some(500),
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 1, config);
+        for _ in 0..5 {
+            assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 1.0));
+        }
+
+        assert!(chain.generate_new_block());
+
+        let block = chain.block(1).unwrap();
+        assert!(block_bytes(block) <= 500);
+        assert!(block.transactions.len() > 1);
+        assert_eq!(block.count as usize, block.transactions.len());
+        // What did not fit is still pending, in order
+        assert_eq!(
+            block.transactions.len() - 1 + chain.current_transaction.len(),
+            5
+        );
+        assert!(!chain.current_transaction.is_empty());
+        assert_eq!(chain.validate_chain(&chain.chain), Ok(()));
+    }
+
+    #[test]
+    fn mining_refuses_when_even_the_coinbase_does_not_fit() {
+        let mut chain = test_chain();
+        let size = block_bytes(chain.block(0).unwrap());
+        chain.config.max_block_bytes = Some(size / 2);
+
+        assert!(!chain.generate_new_block());
+        assert_eq!(chain.chain.len(), 1);
+    }
+
+    #[test]
+    fn oversized_blocks_are_rejected_before_the_seal_is_checked() {
+        let mut chain = test_chain();
+        let transfers = (0..20)
+            .map(|_| Transaction::new(String::from("miner"), String::from("bob"), 1.0))
+            .collect();
+        let mut block = sealed_block(&chain, "miner", transfers);
+        // Neither the proof of work nor the merkle root hold any more
+        block.header.merkle_root = String::from("tampered");
+        chain.config.max_block_bytes = Some(block_bytes(&block) - 1);
+
+        assert_eq!(
+            chain.validate_block(&block, false),
+            Err(BlockError::TooLarge)
+        );
+    }
+
+    fn assert_same_state(a: &NodeState, b: &NodeState) {
+        assert_eq!(format!("{:?}", a.config), format!("{:?}", b.config));
+        assert_eq!(a.records, b.records);
+        assert_eq!(
+            serde_json::to_value(&a.chain).unwrap(),
+            serde_json::to_value(&b.chain).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&a.current_transaction).unwrap(),
+            serde_json::to_value(&b.current_transaction).unwrap()
+        );
+        assert_eq!(a.difficulty, b.difficulty);
+        assert_eq!(a.miner_address, b.miner_address);
+        assert_eq!(a.reward, b.reward);
+    }
+
+    #[test]
+    fn restore_state_undoes_a_divergent_branch() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        let checkpoint = chain.checkpoint_state();
+
+        chain.generate_new_block();
+        assert!(chain.new_transaction(String::from("bob"), String::from("carol"), 2.0));
+        chain.mine_to(String::from("other")).unwrap();
+        chain.update_reward(10.0);
+
+        chain.restore_state(checkpoint.clone());
+
+        assert_same_state(&chain.checkpoint_state(), &checkpoint);
+        assert_eq!(chain.last_hash(), Chain::hash(&checkpoint.chain[1].header));
+    }
+
+    #[test]
+    fn throughput_counts_transfers_per_second_of_the_window() {
+        let mut chain = chain_mined_at(&[0]);
+        for (secs, transfers) in [(10, 3), (20, 1), (30, 0)] {
+            for _ in 0..transfers {
+                assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 1.0));
+            }
+            chain.mine_block(at(secs), String::new(), String::from("miner"));
+        }
+
+        assert_eq!(chain.throughput(2), 1.0 / 20.0);
+        assert_eq!(chain.throughput(3), 4.0 / 30.0);
+        assert_eq!(chain.throughput(10), chain.throughput(3));
+        assert_eq!(chain.throughput(0), 0.0);
+    }
+
+    #[test]
+    fn transaction_absent_is_false_only_for_included_transactions() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+        let included = chain.block(1).unwrap().transactions[1].id();
+        let other = Transaction::new(String::from("miner"), String::from("bob"), 6.0).id();
+
+        assert!(!chain.transaction_absent(1, &included));
+        assert!(chain.transaction_absent(1, &other));
+        assert!(chain.transaction_absent(0, &included));
+        assert!(chain.transaction_absent(9, &included));
+    }
+
+    #[test]
+    fn mining_completes_with_a_yield_interval() {
+        let config = ChainConfig {
+            mining_yield_interval: 1,
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 2, config);
+
+        assert!(chain.generate_new_block());
+        assert_eq!(chain.validate_chain(&chain.chain), Ok(()));
+    }
+
+    #[test]
+    fn more_leading_zeros_means_more_header_work() {
+        let chain = test_chain();
+        let mut header = chain.block(0).unwrap().header.clone();
+        header.difficulty = 3;
+        chain.pow.solve(&mut header);
+        let strong = header.clone();
+
+        // A nonce whose hash starts with a nonzero digit
+        while Chain::hash(&header).starts_with('0') {
+            header.nonce += 1;
+        }
+
+        assert!(Chain::hash(&strong).starts_with("000"));
+        assert!(Chain::header_work(&strong) > Chain::header_work(&header));
+    }
+
+    #[test]
+    fn time_window_mines_one_block_with_the_whole_batch() {
+        let mut chain = test_chain();
+        let (sender, incoming) = mpsc::channel();
+        for amount in [1.0, 2.0, 3.0] {
+            let tx = Transaction::new(String::from("miner"), String::from("bob"), amount);
+            sender.send(tx).unwrap();
+        }
+
+        // Every poll of the loop advances the clock by one second, the loop stops
+        // after 30 of them, long after the 10 second window closed
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        let polls = Cell::new(0);
+        let clock = || {
+            polls.set(polls.get() + 1);
+            if polls.get() >= 30 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            start + Duration::from_secs(polls.get())
+        };
+
+        let window = BatchWindow::Time(Duration::from_secs(10));
+        chain.auto_mine_loop_with_clock(window, &incoming, &stop, &clock);
+
+        assert_eq!(chain.chain.len(), 2);
+        let amounts: Vec<f32> = chain.chain[1].transactions[1..]
+            .iter()
+            .map(|t| t.amount)
+            .collect();
+        assert_eq!(amounts, vec![1.0, 2.0, 3.0]);
+        assert!(chain.current_transaction.is_empty());
+        drop(sender);
+    }
+
+    #[test]
+    fn blocks_mined_per_address_counts_coinbases() {
+        let mut chain = test_chain();
+        for _ in 0..3 {
+            chain.mine_to(String::from("alice")).unwrap();
+        }
+        chain.mine_to(String::from("bob")).unwrap();
+
+        let mined = chain.blocks_mined_per_address();
+        assert_eq!(mined.get("alice"), Some(&3));
+        assert_eq!(mined.get("bob"), Some(&1));
+        // Genesis
+        assert_eq!(mined.get("miner"), Some(&1));
+        assert_eq!(mined.len(), 3);
+        assert!(chain.is_miner("bob"));
+        assert!(!chain.is_miner("carol"));
+    }
+
+    #[test]
+    fn proof_of_work_returns_the_hash_of_the_sealed_header() {
+        let chain = test_chain();
+        let mut header = chain.block(0).unwrap().header.clone();
+        header.difficulty = 2;
+        header.nonce = 0;
+
+        let hash = Chain::proof_of_work(&mut header, 0);
+
+        assert!(hash.starts_with("00"));
+        assert!(Chain::meets_difficulty(&Chain::hash_bytes(&header), 2));
+        assert_eq!(hash, Chain::hash(&header));
+        let mut solved = header.clone();
+        solved.nonce = 0;
+        assert_eq!(chain.pow.solve(&mut solved), hash);
+        assert_eq!(solved.nonce, header.nonce);
+    }
+
+    #[test]
+    fn last_hash_follows_the_tip() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        chain.generate_new_block();
+        assert_eq!(chain.last_hash(), Chain::hash(&chain.chain[2].header));
+
+        chain.truncate_to(1).unwrap();
+        assert_eq!(chain.last_hash(), Chain::hash(&chain.chain[1].header));
+
+        chain.recompute_merkle_roots();
+        assert_eq!(chain.last_hash(), Chain::hash(&chain.chain[1].header));
+    }
+
+    #[test]
+    fn security_warning_flags_low_difficulties() {
+        let config = ChainConfig {
+            min_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 0, config);
+        assert!(chain.security_warning().is_some());
+
+        assert!(chain.update_difficulty(1));
+        assert!(chain.security_warning().is_some());
+        assert!(chain.update_difficulty(2));
+        assert_eq!(chain.security_warning(), None);
+    }
+
+    #[test]
+    fn verify_segment_checks_links_from_the_anchor() {
+        let mut chain = test_chain();
+        for _ in 0..3 {
+            chain.generate_new_block();
+        }
+        let anchor = Chain::hash(&chain.chain[0].header);
+        let segment = &chain.chain[1..];
+
+        assert_eq!(chain.verify_segment(&anchor, segment), Ok(()));
+        assert_eq!(
+            chain.verify_segment(&Chain::zero_hash(), segment),
+            Err(SegmentError {
+                index: 0,
+                error: BlockError::BrokenLink
+            })
+        );
+
+        let mut broken = segment.to_vec();
+        broken[2].header.pre_hash = anchor.clone();
+        chain.pow.solve(&mut broken[2].header);
+        assert_eq!(
+            chain.verify_segment(&anchor, &broken),
+            Err(SegmentError {
+                index: 2,
+                error: BlockError::BrokenLink
+            })
+        );
+    }
+
+    #[test]
+    fn a_chain_of_difficulty_zero_blocks_does_not_validate() {
+        let config = ChainConfig {
+            min_difficulty: 0,
+            ..ChainConfig::default()
+        };
+        let mut weak = Chain::with_config(String::from("miner"), 0, config);
+        weak.generate_new_block();
+        assert_eq!(weak.validate_chain(&weak.chain), Ok(()));
+
+        let chain = test_chain();
+        assert_eq!(
+            chain.validate_chain(&weak.chain),
+            Err(ChainError::InvalidBlock(SegmentError {
+                index: 0,
+                error: BlockError::WrongDifficulty
+            }))
+        );
+    }
+
+    #[test]
+    fn difficulty_cannot_go_below_the_floor() {
+        let mut chain = test_chain();
+
+        assert!(!chain.update_difficulty(0));
+        assert_eq!(chain.difficulty(), 1);
+        assert!(chain.update_difficulty(2));
+    }
+
+    #[test]
+    fn validate_chain_enforces_the_work_checkpoint() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        chain.generate_new_block();
+        let blocks = chain.chain.clone();
+
+        chain.config.work_checkpoint = Some((1, 32));
+        assert_eq!(chain.validate_chain(&blocks), Ok(()));
+
+        chain.config.work_checkpoint = Some((1, 48));
+        assert_eq!(
+            chain.validate_chain(&blocks),
+            Err(ChainError::CheckpointMismatch)
+        );
+
+        // Too short to reach the checkpoint
+        chain.config.work_checkpoint = Some((5, 96));
+        assert_eq!(
+            chain.validate_chain(&blocks),
+            Err(ChainError::CheckpointMismatch)
+        );
+    }
+
+    #[test]
+    fn golden_vectors() {
+        assert_eq!(self_test(), Ok(()));
+    }
+
+    #[test]
+    fn only_coinbase_receivers_are_miners() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+
+        assert!(chain.is_miner("miner"));
+        assert!(!chain.is_miner("bob"));
+    }
+
+    #[test]
+    fn write_jsonl_writes_one_parsable_block_per_line() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+        chain.generate_new_block();
+
+        let mut buffer = Vec::new();
+        chain.write_jsonl(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+
+        let blocks: Vec<Block> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(text.ends_with('\n'));
+        assert_eq!(blocks.len(), chain.chain.len());
+        for (parsed, block) in blocks.iter().zip(&chain.chain) {
+            assert_eq!(Chain::hash(&parsed.header), Chain::hash(&block.header));
+            assert_eq!(parsed.transactions.len(), block.transactions.len());
+        }
+    }
+
+    #[test]
+    fn transactions_between_keeps_blocks_inside_the_window() {
+        let mut chain = chain_mined_at(&[0]);
+        for (secs, amount) in [(10, 1.0), (20, 2.0), (30, 3.0)] {
+            assert!(chain.new_transaction(String::from("miner"), String::from("bob"), amount));
+            chain.mine_block(at(secs), String::new(), String::from("miner"));
+        }
+
+        let found = chain.transactions_between(at(10), at(30));
+        let summary: Vec<(usize, f32)> = found.iter().map(|(h, t)| (*h, t.amount)).collect();
+        // The end of the window is exclusive, coinbases are included
+        assert_eq!(summary, vec![(1, 100.0), (1, 1.0), (2, 100.0), (2, 2.0)]);
+        assert!(chain.transactions_between(at(31), at(40)).is_empty());
+    }
+
+    #[test]
+    fn two_transfers_cannot_overdraw_one_sender() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+        // Each fits in the 300 the miner holds with this block's reward, not both
+        let transfers = vec![
+            Transaction::new(String::from("miner"), String::from("bob"), 200.0),
+            Transaction::new(String::from("miner"), String::from("carol"), 150.0),
+        ];
+        let block = sealed_block(&chain, "miner", transfers);
+
+        assert_eq!(
+            chain.validate_block(&block, false),
+            Err(BlockError::Overdraw)
+        );
+    }
+
+    #[test]
+    fn block_transfers_get_the_transaction_checks() {
+        let chain = test_chain();
+        let check = |transfer: Transaction| {
+            let block = sealed_block(&chain, "miner", vec![transfer]);
+            chain.validate_block(&block, false)
+        };
+
+        assert_eq!(
+            check(Transaction::new(
+                String::from("ghost"),
+                String::from("bob"),
+                0.0
+            )),
+            Err(BlockError::InvalidTransaction(
+                TransactionError::InvalidAmount
+            ))
+        );
+        assert_eq!(
+            check(Transaction::new(
+                String::from("ghost"),
+                String::from("bob"),
+                1.0
+            )),
+            Err(BlockError::InvalidTransaction(
+                TransactionError::UnknownSender
+            ))
+        );
+        assert_eq!(
+            check(Transaction::new(
+                String::from("bob"),
+                String::from("miner"),
+                -50.0
+            )),
+            Err(BlockError::InvalidTransaction(
+                TransactionError::InvalidAmount
+            ))
+        );
+        assert_eq!(
+            check(Transaction::new(
+                String::from("miner"),
+                String::from("bob"),
+                f32::NAN
+            )),
+            Err(BlockError::InvalidTransaction(
+                TransactionError::InvalidAmount
+            ))
+        );
+        assert_eq!(
+            check(Transaction::new(
+                String::from("miner"),
+                String::from("bob"),
+                5.0
+            )),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn address_bloom_never_misses_a_member() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("alice"), 5.0));
+        assert!(chain.new_transaction(String::from("alice"), String::from("bob"), 2.0));
+        chain.generate_new_block();
+
+        let filter = chain.address_bloom(1).unwrap();
+        for address in ["Root", "miner", "alice", "bob"] {
+            assert!(filter.may_contain(address));
+        }
+
+        let false_positives = (0..1000)
+            .filter(|i| filter.may_contain(&format!("stranger-{}", i)))
+            .count();
+        assert!(false_positives < 100, "{} false positives", false_positives);
+        assert!(chain.address_bloom(2).is_none());
+    }
+
+    #[test]
+    fn block_delta_for_nets_payments_both_ways() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("alice"), 30.0));
+        assert!(chain.new_transaction(String::from("alice"), String::from("miner"), 10.0));
+        assert!(chain.new_transaction(String::from("alice"), String::from("bob"), 5.0));
+        chain.generate_new_block();
+
+        // Reward 100, sent 30, received 10
+        assert_eq!(chain.block_delta_for(1, "miner"), 80.0);
+        assert_eq!(chain.block_delta_for(1, "alice"), 15.0);
+        assert_eq!(chain.block_delta_for(1, "carol"), 0.0);
+        assert_eq!(chain.block_delta_for(7, "miner"), 0.0);
+    }
+
+    // The hex string check proof_of_work used before it compared bytes
+    fn hex_meets_difficulty(hash: &str, difficulty: u32) -> bool {
+        let slice = &hash[..difficulty as usize];
+        matches!(slice.parse::<u32>(), Ok(0))
+    }
+
+    #[test]
+    fn byte_and_hex_difficulty_checks_agree() {
+        for i in 0..2000u32 {
+            let mut digest = Chain::hash_bytes(&i);
+            // Zero a varying number of leading nibbles so every difficulty gets hits
+            let zeros = (i % 9) as usize;
+            for nibble in 0..zeros {
+                digest[nibble / 2] &= if nibble % 2 == 0 { 0x0f } else { 0xf0 };
+            }
+            let hash = Chain::hex_to_string(&digest);
+
+            for difficulty in 1..=MAX_DIFFICULTY {
+                assert_eq!(
+                    Chain::meets_difficulty(&digest, difficulty),
+                    hex_meets_difficulty(&hash, difficulty),
+                    "{} at difficulty {}",
+                    hash,
+                    difficulty
+                );
+            }
+        }
+
+        assert!(Chain::meets_difficulty(&[0; 32], MAX_DIFFICULTY));
+        assert!(!Chain::meets_difficulty(&[0; 32], MAX_DIFFICULTY + 1));
+    }
+
+    #[test]
+    fn difficulty_above_the_hash_length_is_refused() {
+        let mut chain = test_chain();
+
+        assert!(!chain.update_difficulty(MAX_DIFFICULTY + 1));
+        assert_eq!(chain.difficulty(), 1);
+    }
+
+    #[test]
+    fn recent_blocks_returns_the_tail_in_order() {
+        let mut chain = test_chain();
+        for _ in 0..4 {
+            chain.generate_new_block();
+        }
+
+        let hashes = |blocks: &[Block]| -> Vec<String> {
+            blocks
+                .iter()
+                .map(|block| Chain::hash(&block.header))
+                .collect()
+        };
+        assert_eq!(hashes(chain.recent_blocks(3)), hashes(&chain.chain[2..]));
+        assert_eq!(hashes(chain.recent_blocks(100)), hashes(&chain.chain));
+        assert!(chain.recent_blocks(0).is_empty());
+    }
+
+    // Fresh directory under the system temp dir, removed first if a previous run left it
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn saved_chain_loads_back_equal_and_valid() {
+        let mut chain = test_chain();
+        assert!(chain.new_transaction(String::from("miner"), String::from("bob"), 30.0));
+        chain.generate_new_block();
+        chain.update_reward(50.0);
+        assert!(chain.new_transaction(String::from("bob"), String::from("carol"), 10.0));
+        chain.generate_new_block();
+
+        let dir = temp_dir("toy-blockchain-round-trip");
+        chain.save_to_dir(&dir).unwrap();
+        let loaded = Chain::load_from_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&loaded.chain).unwrap(),
+            serde_json::to_value(&chain.chain).unwrap()
+        );
+        assert_eq!(loaded.balances_sorted(), chain.balances_sorted());
+        assert_eq!(loaded.last_hash(), chain.last_hash());
+        assert_eq!(loaded.difficulty(), chain.difficulty());
+        assert_eq!(loaded.reward(), chain.reward());
+        assert_eq!(loaded.miner_address, chain.miner_address);
+        assert_eq!(loaded.validate_chain(&loaded.chain), Ok(()));
+        assert_eq!(
+            loaded.validate_block(&sealed_block(&loaded, "miner", vec![]), true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn loading_rejects_a_block_spending_from_an_unknown_sender() {
+        let mut chain = test_chain();
+        let transfer = Transaction::new(String::from("ghost"), String::from("bob"), 10.0);
+        let block = sealed_block(&chain, "miner", vec![transfer]);
+        // Links and seal are fine, so only the replayed balances catch it
+        chain.chain.push(block);
+        chain.rehash_tip();
+        assert_eq!(
+            chain.validate_chain(&chain.chain),
+            Err(ChainError::InvalidBlock(SegmentError {
+                index: 1,
+                error: BlockError::InvalidTransaction(TransactionError::UnknownSender)
+            }))
+        );
+
+        let dir = temp_dir("toy-blockchain-tampered");
+        chain.save_to_dir(&dir).unwrap();
+        let loaded = Chain::load_from_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let err = loaded.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("block 1 "), "{}", err);
+    }
+
+    #[test]
+    fn mine_to_fails_when_an_empty_block_is_too_large() {
+        let mut chain = test_chain();
+        let size = block_bytes(chain.block(0).unwrap());
+        chain.config.max_block_bytes = Some(size / 2);
+
+        assert_eq!(
+            chain.mine_to(String::from("pool")).err(),
+            Some(MineError::TooLarge)
+        );
+        assert_eq!(chain.chain.len(), 1);
+        assert_eq!(chain.balance("pool"), None);
+    }
+
+    #[test]
+    fn mining_moves_a_stale_timestamp_past_the_median() {
+        // Genesis a day ahead of the clock
+        let future = SystemTime::now() + Duration::from_secs(86_400);
+        let config = ChainConfig {
+            genesis_timestamp: Some(future),
+            ..ChainConfig::default()
+        };
+        let mut chain = Chain::with_config(String::from("miner"), 1, config);
+        let candidate = sealed_block(&chain, "miner", vec![]);
+        assert_eq!(
+            chain.validate_block(&candidate, false),
+            Err(BlockError::TimestampTooOld)
+        );
+
+        assert!(chain.generate_new_block());
+        // A clock going backwards
+        chain.mine_block(at(1_000), String::new(), String::from("miner"));
+
+        assert!(chain.chain[1].header.timestamp > future);
+        assert!(chain.chain[2].header.timestamp > chain.chain[1].header.timestamp);
+
+        let dir = temp_dir("toy-blockchain-stale-timestamps");
+        chain.save_to_dir(&dir).unwrap();
+        let loaded = Chain::load_from_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.is_ok());
+    }
+
+    #[test]
+    fn validate_chain_replays_coinbases_and_overdraws() {
+        let mut chain = test_chain();
+        chain.generate_new_block();
+
+        // Two rewards in one block
+        let mut extra_reward = chain.chain.clone();
+        let coinbase = extra_reward[1].transactions[0].clone();
+        extra_reward[1].transactions.push(coinbase);
+        extra_reward[1].header.merkle_root =
+            Chain::get_merkle(extra_reward[1].transactions.clone());
+        chain.pow.solve(&mut extra_reward[1].header);
+        assert_eq!(
+            chain.validate_chain(&extra_reward),
+            Err(ChainError::InvalidBlock(SegmentError {
+                index: 1,
+                error: BlockError::InvalidCoinbase
+            }))
+        );
+
+        // More than the 200 the miner holds after block 1
+        let transfer = Transaction::new(String::from("miner"), String::from("bob"), 250.0);
+        let overdraw = seal(
+            &chain,
+            vec![chain.reward_transaction(String::from("bob")), transfer],
+            chain.difficulty,
+        );
+        let mut blocks = chain.chain.clone();
+        blocks.push(overdraw);
+        assert_eq!(
+            chain.validate_chain(&blocks),
+            Err(ChainError::InvalidBlock(SegmentError {
+                index: 2,
+                error: BlockError::Overdraw
+            }))
+        );
+    }
+
+    #[test]
+    fn running_out_of_nonces_moves_the_timestamp_forward() {
+        let chain = test_chain();
+        let mut header = chain.block(0).unwrap().header.clone();
+        header.difficulty = 2;
+        header.nonce = u32::MAX - 3;
+        let timestamp = header.timestamp;
+
+        let hash = Chain::proof_of_work(&mut header, 0);
+
+        assert_eq!(header.timestamp, timestamp + Duration::from_secs(1));
+        assert!(header.nonce < u32::MAX - 3);
+        assert!(hash.starts_with("00"));
+        assert_eq!(hash, Chain::hash(&header));
+    }
+
+    // Timing comparison, run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_byte_and_hex_difficulty_checks() {
+        let digests: Vec<[u8; 32]> = (0..200_000u32).map(|i| Chain::hash_bytes(&i)).collect();
+
+        let start = Instant::now();
+        let bytes = digests
+            .iter()
+            .filter(|digest| Chain::meets_difficulty(digest, 3))
+            .count();
+        let byte_time = start.elapsed();
+
+        // The hex check needs the string first, one allocation per attempt
+        let start = Instant::now();
+        let hex = digests
+            .iter()
+            .filter(|digest| hex_meets_difficulty(&Chain::hex_to_string(&digest[..]), 3))
+            .count();
+        let hex_time = start.elapsed();
+
+        assert_eq!(bytes, hex);
+        println!(
+            "{} digests: bytes {:?}, hex {:?}",
+            digests.len(),
+            byte_time,
+            hex_time
+        );
+    }
+
+    // Saves a fresh chain and replaces its manifest with `manifest`
+    fn load_with_manifest(name: &str, manifest: serde_json::Value) -> io::Result<Chain> {
+        let dir = temp_dir(name);
+        test_chain().save_to_dir(&dir).unwrap();
+        fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+
+        let loaded = Chain::load_from_dir(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn loading_rejects_a_corrupt_manifest() {
+        let manifest = |blocks: u64, difficulty: u32, reward: f32| {
+            serde_json::json!({
+                "blocks": blocks,
+                "miner_address": "miner",
+                "difficulty": difficulty,
+                "reward": reward,
+            })
+        };
+
+        let huge = load_with_manifest("toy-blockchain-huge", manifest(u64::MAX, 1, 100.0));
+        assert_eq!(huge.err().unwrap().kind(), io::ErrorKind::NotFound);
+
+        let too_easy = load_with_manifest("toy-blockchain-easy", manifest(1, 0, 100.0));
+        assert_eq!(too_easy.err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let too_hard = load_with_manifest("toy-blockchain-hard", manifest(1, 65, 100.0));
+        assert_eq!(too_hard.err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let no_reward = load_with_manifest("toy-blockchain-reward", manifest(1, 1, 0.0));
+        assert_eq!(no_reward.err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        assert!(load_with_manifest("toy-blockchain-ok", manifest(1, 1, 100.0)).is_ok());
     }
 }
@@ -0,0 +1,2 @@
+pub mod blockchain;
+pub mod rpc;
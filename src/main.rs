@@ -1,11 +1,8 @@
-#[macro_use]
-extern crate serde_derive;
-
 use std::io;
 use std::process;
 use std::io::Write;
 
-mod blockchain;
+use ::blockchain::{blockchain, rpc};
 
 // TODO: VALIDATE MERKLE ROOT
 // TODO: VALIDATE IF ADDRESS HAS SUFFICIENT MONEY
@@ -18,16 +15,32 @@ fn main() {
     let mut choice = String::new();
 
     print!("Input a miner address: ");
-    io::stdout().flush();
-    io::stdin().read_line(&mut miner_address);
-    print!("Difficulty: ");
-    io::stdout().flush();
-    io::stdin().read_line(&mut difficulty);
+    io::stdout().flush().unwrap();
+    io::stdin().read_line(&mut miner_address).unwrap();
+
+    // Chain::new refuses difficulties outside this range, so ask again until it fits
+    let min_difficulty = blockchain::ChainConfig::default().min_difficulty;
+    let diff = loop {
+        print!("Difficulty: ");
+        io::stdout().flush().unwrap();
+        difficulty.clear();
+        io::stdin().read_line(&mut difficulty).unwrap();
 
-    let diff = difficulty.trim().parse::<u32>().expect("We need an integer");
+        match difficulty.trim().parse::<u32>() {
+            Ok(diff) if diff >= min_difficulty && diff <= blockchain::MAX_DIFFICULTY => break diff,
+            _ => println!(
+                "The difficulty must be an integer from {} to {}",
+                min_difficulty,
+                blockchain::MAX_DIFFICULTY
+            ),
+        }
+    };
     println!("Generating a genesis block...");
 
     let mut chain = blockchain::Chain::new(miner_address.trim().to_string(), diff);
+    if let Some(warning) = chain.security_warning() {
+        println!("Warning: {}", warning);
+    }
 
     loop {
         println!("Menu");
@@ -35,12 +48,13 @@ fn main() {
         println!("2) Mine Block");
         println!("3) Change difficulty");
         println!("4) Change reward");
+        println!("5) JSON-RPC request");
         println!("0) Exit");
         print!("Enter your choice: ");
-        io::stdout().flush();
+        io::stdout().flush().unwrap();
         choice.clear();
-        io::stdin().read_line(&mut choice);
-        println!("");
+        io::stdin().read_line(&mut choice).unwrap();
+        println!();
 
         match choice.trim().parse().unwrap() {
             0 =>
@@ -54,14 +68,14 @@ fn main() {
                 let mut amount = String::new();
 
                 print!("enter sender address:");
-                io::stdout().flush();
-                io::stdin().read_line(&mut sender);
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut sender).unwrap();
                 print!("enter receiver address: ");
-                io::stdout().flush();
-                io::stdin().read_line(&mut receiver);
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut receiver).unwrap();
                 print!("Enter amount: ");
-                io::stdout().flush();
-                io::stdin().read_line(&mut amount);
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut amount).unwrap();
 
                 let res = chain.new_transaction(sender.trim().to_string(), 
                                         receiver.trim().to_string(), 
@@ -85,8 +99,8 @@ fn main() {
             {
                 let mut new_diff = String::new();
                 print!("enter new difficulty: ");
-                io::stdout().flush();
-                io::stdin().read_line(&mut new_diff);
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut new_diff).unwrap();
                 let res = chain.update_difficulty(new_diff.trim().parse().unwrap());
                 match res {
                     true => println!("Updated Difficulty"),
@@ -96,14 +110,24 @@ fn main() {
             4 =>{
                 let mut new_reward = String::new();
                 print!("Enter new reward: ");
-                io::stdout().flush();
-                io::stdin().read_line(&mut new_reward);
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut new_reward).unwrap();
                 let res = chain.update_reward(new_reward.trim().parse().unwrap());
                 match res {
                     true => println!("Updated reward"),
                     false => println!("Failed Update reward"),
                 }
             }
+            5 => {
+                let mut request = String::new();
+                print!("Enter request: ");
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut request).unwrap();
+                match rpc::handle_request(&mut chain, request.trim()) {
+                    Some(response) => println!("{}", response),
+                    None => println!("Notification handled"),
+                }
+            }
             _ => println!("Invalid option please retry"),
         }
 
@@ -0,0 +1,246 @@
+use crate::blockchain::Chain;
+use serde_json::{json, Value};
+
+// JSON-RPC 2.0 error codes
+// https://www.jsonrpc.org/specification#error_object
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Start of the range reserved for implementation defined server errors
+const TRANSACTION_REJECTED: i64 = -32000;
+const MINING_FAILED: i64 = -32001;
+
+// Handles a JSON-RPC 2.0 request (or batch) and returns the serialized response.
+// Notifications, requests without an id, get no response, hence None.
+pub fn handle_request(chain: &mut Chain, request: &str) -> Option<String> {
+    let request: Value = match serde_json::from_str(request) {
+        Ok(request) => request,
+        Err(_) => return Some(error(Value::Null, PARSE_ERROR, "Parse error").to_string()),
+    };
+
+    match request {
+        Value::Array(batch) if batch.is_empty() => {
+            Some(error(Value::Null, INVALID_REQUEST, "Invalid Request").to_string())
+        }
+        Value::Array(batch) => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|request| handle_single(chain, request))
+                .collect();
+
+            if responses.is_empty() {
+                None
+            } else {
+                Some(Value::Array(responses).to_string())
+            }
+        }
+        request => handle_single(chain, request).map(|response| response.to_string()),
+    }
+}
+
+fn handle_single(chain: &mut Chain, request: Value) -> Option<Value> {
+    let id = request.get("id").cloned();
+    let version = request.get("jsonrpc").and_then(Value::as_str);
+    let method = request.get("method").and_then(Value::as_str);
+
+    let method = match (version, method) {
+        (Some("2.0"), Some(method)) => method,
+        _ => {
+            return Some(error(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "Invalid Request",
+            ))
+        }
+    };
+
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let result = call(chain, method, &params);
+
+    // A notification is still executed, it just gets no reply
+    let id = id?;
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err((code, message)) => error(id, code, &message),
+    })
+}
+
+fn call(chain: &mut Chain, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    match method {
+        "getBlock" => {
+            let height = param(params, 0, "height")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid_params("expected a block height"))?;
+
+            match chain.block(height as usize) {
+                Some(block) => Ok(json!(block)),
+                None => Err(invalid_params("no block at this height")),
+            }
+        }
+        "getBalance" => {
+            let address = param(params, 0, "address")
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid_params("expected an address"))?;
+
+            Ok(json!(chain.balance(address).unwrap_or(0.0)))
+        }
+        "sendTransaction" => {
+            let sender = param(params, 0, "sender").and_then(Value::as_str);
+            let receiver = param(params, 1, "receiver").and_then(Value::as_str);
+            let amount = param(params, 2, "amount").and_then(Value::as_f64);
+
+            let (sender, receiver, amount) = match (sender, receiver, amount) {
+                (Some(sender), Some(receiver), Some(amount)) => (sender, receiver, amount),
+                _ => return Err(invalid_params("expected sender, receiver and amount")),
+            };
+
+            if chain.new_transaction(sender.to_string(), receiver.to_string(), amount as f32) {
+                Ok(json!(true))
+            } else {
+                Err((TRANSACTION_REJECTED, String::from("transaction rejected")))
+            }
+        }
+        "mine" => {
+            if !chain.generate_new_block() {
+                return Err((MINING_FAILED, String::from("block could not be mined")));
+            }
+            Ok(json!({ "height": chain.height(), "hash": chain.last_hash() }))
+        }
+        "getChainInfo" => Ok(json!({
+            "height": chain.height(),
+            "tip": chain.last_hash(),
+            "difficulty": chain.difficulty(),
+            "reward": chain.reward(),
+            "pending": chain.export_mempool().len(),
+        })),
+        _ => Err((METHOD_NOT_FOUND, String::from("Method not found"))),
+    }
+}
+
+// Params can be given by position or by name
+fn param<'a>(params: &'a Value, index: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Array(values) => values.get(index),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    }
+}
+
+fn invalid_params(message: &str) -> (i64, String) {
+    (INVALID_PARAMS, format!("Invalid params: {}", message))
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn respond(chain: &mut Chain, request: &str) -> Value {
+        serde_json::from_str(&handle_request(chain, request).unwrap()).unwrap()
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response["error"]["code"].as_i64()
+    }
+
+    #[test]
+    fn well_formed_requests_get_a_result_with_their_id() {
+        let mut chain = Chain::new(String::from("miner"), 1);
+
+        let response = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"getBalance","params":["miner"],"id":7}"#,
+        );
+        assert_eq!(
+            response,
+            json!({ "jsonrpc": "2.0", "result": 100.0, "id": 7 })
+        );
+
+        let response = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"sendTransaction","params":{"sender":"miner","receiver":"bob","amount":5},"id":"a"}"#,
+        );
+        assert_eq!(response["result"], json!(true));
+        assert_eq!(response["id"], json!("a"));
+        assert!(response.get("error").is_none());
+
+        let response = respond(&mut chain, r#"{"jsonrpc":"2.0","method":"mine","id":8}"#);
+        assert_eq!(response["result"]["height"], json!(chain.height()));
+
+        let response = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"getBlock","params":[1],"id":9}"#,
+        );
+        assert_eq!(response["result"]["count"], json!(2));
+    }
+
+    #[test]
+    fn errors_carry_the_spec_codes() {
+        let mut chain = Chain::new(String::from("miner"), 1);
+
+        let bad_params = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"getBlock","params":["one"],"id":1}"#,
+        );
+        assert_eq!(error_code(&bad_params), Some(INVALID_PARAMS));
+        assert_eq!(bad_params["id"], json!(1));
+        assert!(bad_params.get("result").is_none());
+
+        let missing_block = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"getBlock","params":[5],"id":2}"#,
+        );
+        assert_eq!(error_code(&missing_block), Some(INVALID_PARAMS));
+
+        let unknown = respond(&mut chain, r#"{"jsonrpc":"2.0","method":"nope","id":3}"#);
+        assert_eq!(error_code(&unknown), Some(METHOD_NOT_FOUND));
+
+        let rejected = respond(
+            &mut chain,
+            r#"{"jsonrpc":"2.0","method":"sendTransaction","params":["bob","miner",1],"id":4}"#,
+        );
+        assert_eq!(error_code(&rejected), Some(TRANSACTION_REJECTED));
+
+        let no_version = respond(&mut chain, r#"{"method":"mine","id":5}"#);
+        assert_eq!(error_code(&no_version), Some(INVALID_REQUEST));
+
+        let garbage = respond(&mut chain, "{not json");
+        assert_eq!(error_code(&garbage), Some(PARSE_ERROR));
+        assert_eq!(garbage["id"], Value::Null);
+    }
+
+    #[test]
+    fn mine_reports_a_block_that_could_not_be_mined() {
+        let mut chain = Chain::new(String::from("miner"), 1);
+        chain.update_max_block_bytes(Some(10));
+
+        let response = respond(&mut chain, r#"{"jsonrpc":"2.0","method":"mine","id":1}"#);
+        assert_eq!(error_code(&response), Some(MINING_FAILED));
+        assert!(response.get("result").is_none());
+        assert_eq!(chain.height(), 0);
+    }
+
+    #[test]
+    fn notifications_run_but_get_no_response() {
+        let mut chain = Chain::new(String::from("miner"), 1);
+
+        let response = handle_request(&mut chain, r#"{"jsonrpc":"2.0","method":"mine"}"#);
+        assert_eq!(response, None);
+        assert_eq!(chain.height(), 1);
+
+        let batch = respond(
+            &mut chain,
+            r#"[{"jsonrpc":"2.0","method":"mine"},{"jsonrpc":"2.0","method":"getChainInfo","id":1}]"#,
+        );
+        assert_eq!(batch.as_array().unwrap().len(), 1);
+        assert_eq!(batch[0]["result"]["height"], json!(2));
+    }
+}